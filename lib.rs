@@ -1,272 +1,3232 @@
 // SPDX-License-Identifier: MIT
+#![cfg_attr(not(feature = "std"), no_std)]
+#![feature(min_specialization)]
 
-use ink_lang::contract;
-use ink_prelude::vec::Vec;
-use ink_storage::{
-    collections::{HashMap as StorageHashMap, Vec as StorageVec},
-    lazy::Lazy,
-    traits::{PackedLayout, SpreadLayout},
+use ink::{
+    prelude::{
+        string::ToString,
+        vec::Vec,
+    },
+    storage::{Lazy, Mapping},
+};
+use openbrush::{
+    contracts::{
+        ownable::OwnableError,
+        psp34::PSP34Error,
+    },
+    traits::{AccountId, Balance, Hash, Timestamp},
 };
 
-type TokenId = u32;
-type Balance = u128;
-type ArtistId = u32;
+pub type TokenId = u32;
+pub type ArtistId = u32;
+pub type RangeId = u32;
 
-#[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode, SpreadLayout, PackedLayout)]
-#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
-pub enum Token {
-    Owned {
-        price: Balance,
-        owner: AccountId,
-    },
-    ForSale {
-        price: Balance,
-        artist: ArtistId,
-    },
+/// Basis points making up the whole of an amount, i.e. 100%.
+pub const BASIS_POINTS: u16 = 10_000;
+/// Royalty paid to artists that have not configured their own.
+pub const DEFAULT_ROYALTY_BPS: u16 = 1_000;
+
+/// Bids landing this close to the end of an auction, in milliseconds, push the end
+/// back to this far after the bid.
+pub const AUCTION_EXTENSION_WINDOW: Timestamp = 10 * 60 * 1000;
+
+/// Largest number of items handled by a single batch call.
+pub const MAX_BATCH_SIZE: u32 = 200;
+/// Largest number of accounts a royalty can be split among, bounding the cost of a sale.
+pub const MAX_ROYALTY_RECIPIENTS: u32 = 10;
+/// Largest number of entries returned by a single page of a listing query.
+pub const MAX_PAGE_SIZE: u32 = 100;
+/// Largest number of entries inspected by a single filtered listing query.
+pub const MAX_PAGE_SCAN: u32 = 4 * MAX_PAGE_SIZE;
+
+/// PSP34Metadata attribute keys of the per-token metadata.
+pub const NAME_KEY: &[u8] = b"name";
+pub const DESCRIPTION_KEY: &[u8] = b"description";
+pub const URI_KEY: &[u8] = b"uri";
+pub const CONTENT_HASH_KEY: &[u8] = b"content_hash";
+
+#[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout))]
+pub struct Token {
+    owner: AccountId,
+    artist: ArtistId,
+    listing: Option<Listing>,
 }
 
-#[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode, SpreadLayout, PackedLayout)]
-#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+#[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout))]
+pub struct Listing {
+    price: Balance,
+    seller: AccountId,
+    expiry: Option<Timestamp>,
+    /// Turns the listing into a Dutch auction starting at `price`.
+    decay: Option<PriceDecay>,
+}
+
+/// Declining price of a Dutch auction, falling from the listing price at `start` to
+/// `floor` at `end`, continuously or every `step` milliseconds.
+#[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout))]
+pub struct PriceDecay {
+    floor: Balance,
+    start: Timestamp,
+    end: Timestamp,
+    /// Zero for a linear decay.
+    step: Timestamp,
+}
+
+/// Timed English auction of a token held in custody by the contract.
+#[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout))]
+pub struct Auction {
+    seller: AccountId,
+    reserve: Balance,
+    start: Timestamp,
+    end: Timestamp,
+    min_increment: Balance,
+    /// Highest bidder and their escrowed bid.
+    highest_bid: Option<(AccountId, Balance)>,
+}
+
+/// Sealed-bid auction of a token held in custody by the contract. Bids are committed
+/// until `commit_end` and revealed until `reveal_end`.
+#[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout))]
+pub struct SealedAuction {
+    seller: AccountId,
+    reserve: Balance,
+    commit_end: Timestamp,
+    reveal_end: Timestamp,
+    /// Whether the winner pays the second highest bid instead of their own.
+    second_price: bool,
+    /// Whether the deposits of bidders who never reveal go to the seller.
+    slash_unrevealed: bool,
+    /// Highest revealed bidder and their escrowed bid.
+    highest_bid: Option<(AccountId, Balance)>,
+    /// Second highest revealed bid, or zero.
+    second_bid: Balance,
+}
+
+/// Commitment of a bidder to a sealed-bid auction, backed by a deposit that must
+/// cover the bid.
+#[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout))]
+pub struct SealedBid {
+    commitment: Hash,
+    deposit: Balance,
+    /// Copied from the auction so the deposit can be reclaimed after it is gone.
+    seller: AccountId,
+    reveal_end: Timestamp,
+    slash_unrevealed: bool,
+}
+
+/// Offer on a token, escrowed by the contract until it is accepted or cancelled.
+#[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout))]
+pub struct Offer {
+    amount: Balance,
+    expiry: Timestamp,
+    /// Mint log position of the token the offer was made on, so that the offer does
+    /// not carry over to a token reminted under the same id.
+    mint_position: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout))]
 pub struct Artist {
     name: Vec<u8>,
     account_id: AccountId,
 }
 
-#[contract]
-mod psp34 {
+#[derive(Debug, Clone, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo, ink::storage::traits::StorageLayout))]
+pub struct Royalty {
+    /// Share of the sale price paid as royalty, in basis points.
+    bps: u16,
+    /// Collaborators splitting the royalty, with their share of it in basis points.
+    /// When empty the whole royalty goes to the artist.
+    recipients: Vec<(AccountId, u16)>,
+}
+
+/// Descriptive metadata of an artwork, exposed through `PSP34Metadata` attributes.
+#[derive(Debug, Clone, Default, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub struct TokenMetadata {
+    name: Vec<u8>,
+    description: Vec<u8>,
+    uri: Vec<u8>,
+    content_hash: Option<Hash>,
+    /// Whether the artist may still change the metadata after minting.
+    mutable: bool,
+}
+
+/// Breakdown of who receives what when a token is sold.
+#[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub struct Payout {
+    royalties: Vec<(AccountId, Balance)>,
+    seller_proceeds: Balance,
+}
+
+/// One page of a listing query, with the cursor to pass as `start` to fetch the next
+/// page, or `None` once the end has been reached.
+#[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub struct Page<T> {
+    items: Vec<T>,
+    next: Option<u32>,
+}
+
+/// Returns `bps` basis points of `amount` without overflowing.
+fn bps_of(amount: Balance, bps: u16) -> Balance {
+    let bps = Balance::from(bps);
+    let basis = Balance::from(BASIS_POINTS);
+    amount / basis * bps + amount % basis * bps / basis
+}
+
+/// Computes `amount * numerator / denominator` for `numerator <= denominator` without
+/// overflowing.
+fn fraction_of(amount: Balance, numerator: u64, denominator: u64) -> Balance {
+    let numerator = Balance::from(numerator);
+    let denominator = Balance::from(denominator);
+    amount / denominator * numerator + amount % denominator * numerator / denominator
+}
+
+#[derive(Debug, PartialEq, Eq, scale::Encode, scale::Decode)]
+#[cfg_attr(feature = "std", derive(scale_info::TypeInfo))]
+pub enum Error {
+    /// No token is stored under the given id.
+    TokenNotFound,
+    /// The caller does not own the token.
+    NotOwner,
+    /// The token is not listed for sale.
+    NotForSale,
+    /// The listing of the token has expired.
+    ListingExpired,
+    /// The token already has an active listing.
+    AlreadyListed,
+    /// Listings need a price above zero.
+    InvalidPrice,
+    /// The price of a Dutch auction follows its decay and cannot be changed.
+    DutchListing,
+    /// The expiry of the listing is not in the future.
+    InvalidExpiry,
+    /// The transferred value does not cover the asking price.
+    WrongPrice,
+    /// No artist is registered under the given id.
+    ArtistNotFound,
+    /// The caller is not the account of the artist.
+    NotArtist,
+    /// The caller is already registered as an artist.
+    AlreadyRegistered,
+    /// The token is no longer held by its artist or their minters.
+    TokenSold,
+    /// The batch is empty or holds more than `MAX_BATCH_SIZE` items.
+    InvalidBatchSize,
+    /// No auction is running for the token.
+    AuctionNotFound,
+    /// The auction window is empty or already over.
+    InvalidAuction,
+    /// The auction has not started yet.
+    AuctionNotStarted,
+    /// The auction is over and only waits to be settled.
+    AuctionEnded,
+    /// The auction is still accepting bids.
+    AuctionNotEnded,
+    /// The auction already has bids and can no longer be cancelled.
+    AuctionHasBids,
+    /// The bid is below the reserve or the minimum increment.
+    BidTooLow,
+    /// The sealed-bid auction is not in the phase the call belongs to.
+    WrongPhase,
+    /// The caller already has a bid committed for the token.
+    AlreadyCommitted,
+    /// The caller has no committed bid for the token.
+    BidNotFound,
+    /// The revealed bid does not match the commitment.
+    InvalidReveal,
+    /// The account has no offer on the token.
+    OfferNotFound,
+    /// The offer has expired and can only be cancelled.
+    OfferExpired,
+    /// Sending funds out of the contract failed.
+    TransferFailed,
+    /// The caller has no pending payment to withdraw.
+    NothingToWithdraw,
+    /// The royalty exceeds the cap set at instantiation.
+    RoyaltyTooHigh,
+    /// The shares of the royalty recipients do not add up to 100%.
+    InvalidRoyaltySplit,
+    /// The royalty is split among more than `MAX_ROYALTY_RECIPIENTS` accounts.
+    TooManyRoyaltyRecipients,
+    /// The metadata of the token can no longer be changed.
+    MetadataFrozen,
+    /// An artwork with the same content hash has already been minted.
+    DuplicateContent,
+    /// Error raised by the underlying PSP34 implementation.
+    PSP34Error(PSP34Error),
+    /// Error raised by the underlying Ownable implementation.
+    OwnableError(OwnableError),
+}
+
+impl From<PSP34Error> for Error {
+    fn from(error: PSP34Error) -> Self {
+        Error::PSP34Error(error)
+    }
+}
+
+impl From<OwnableError> for Error {
+    fn from(error: OwnableError) -> Self {
+        Error::OwnableError(error)
+    }
+}
+
+#[openbrush::contract]
+pub mod my_psp34 {
     use super::*;
+    use ink::env::hash::Blake2x256;
+    use openbrush::{
+        contracts::{
+            ownable::*,
+            psp34::{
+                extensions::{
+                    enumerable::*,
+                    metadata::*,
+                },
+                *,
+            },
+        },
+        modifiers,
+        traits::Storage,
+    };
 
     #[ink(storage)]
-    pub struct PSP34 {
-        tokens: StorageHashMap<TokenId, Token>,
-        artists: StorageHashMap<ArtistId, Artist>,
+    #[derive(Default, Storage)]
+    pub struct Contract {
+        #[storage_field]
+        psp34: psp34::Data<enumerable::Balances>,
+        #[storage_field]
+        metadata: metadata::Data,
+        #[storage_field]
+        ownable: ownable::Data,
+        tokens: Mapping<TokenId, Token>,
+        artists: Mapping<ArtistId, Artist>,
+        artist_ids: Mapping<AccountId, ArtistId>,
+        minters: Mapping<(ArtistId, AccountId), ()>,
         next_artist_id: Lazy<ArtistId>,
+        max_royalty_bps: u16,
+        artist_royalties: Mapping<ArtistId, Royalty>,
+        token_royalties: Mapping<TokenId, Royalty>,
+        token_operators: Mapping<TokenId, Vec<AccountId>>,
+        artist_tokens: Mapping<(ArtistId, u32), TokenId>,
+        artist_token_indices: Mapping<TokenId, u32>,
+        artist_token_counts: Mapping<ArtistId, u32>,
+        /// Append-only log of minted tokens; burned tokens leave an empty position.
+        minted_tokens: Mapping<u32, TokenId>,
+        mint_positions: Mapping<TokenId, u32>,
+        minted_count: u32,
+        mutable_metadata: Mapping<TokenId, ()>,
+        content_hashes: Mapping<Hash, TokenId>,
+        auctions: Mapping<TokenId, Auction>,
+        sealed_auctions: Mapping<TokenId, SealedAuction>,
+        sealed_bids: Mapping<(TokenId, AccountId), SealedBid>,
+        offers: Mapping<(TokenId, AccountId), Offer>,
+        pending_payments: Mapping<AccountId, Balance>,
+        range_base_uris: Mapping<RangeId, Vec<u8>>,
+        token_ranges: Mapping<TokenId, RangeId>,
+        next_range_id: Lazy<RangeId>,
+        base_uri: Lazy<Vec<u8>>,
+        uri_suffix: Lazy<Vec<u8>>,
+        metadata_frozen: bool,
     }
 
-    impl PSP34 {
-        #[ink(constructor)]
-        pub fn new() -> Self {
-            Self {
-                tokens: StorageHashMap::new(),
-                artists: StorageHashMap::new(),
-                next_artist_id: Lazy::new(|| 0),
+    #[ink(event)]
+    pub struct Transfer {
+        #[ink(topic)]
+        from: Option<AccountId>,
+        #[ink(topic)]
+        to: Option<AccountId>,
+        #[ink(topic)]
+        id: Id,
+    }
+
+    #[ink(event)]
+    pub struct Approval {
+        #[ink(topic)]
+        from: AccountId,
+        #[ink(topic)]
+        to: AccountId,
+        #[ink(topic)]
+        id: Option<Id>,
+        approved: bool,
+    }
+
+    #[ink(event)]
+    pub struct Minted {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        artist_id: ArtistId,
+        #[ink(topic)]
+        owner: AccountId,
+        price: Balance,
+    }
+
+    #[ink(event)]
+    pub struct BatchMinted {
+        #[ink(topic)]
+        artist_id: ArtistId,
+        #[ink(topic)]
+        owner: AccountId,
+        ids: Vec<TokenId>,
+    }
+
+    #[ink(event)]
+    pub struct Listed {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        seller: AccountId,
+        price: Balance,
+        expiry: Option<Timestamp>,
+    }
+
+    #[ink(event)]
+    pub struct DutchListed {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        seller: AccountId,
+        start_price: Balance,
+        floor_price: Balance,
+        start: Timestamp,
+        end: Timestamp,
+        step: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct PriceChanged {
+        #[ink(topic)]
+        id: TokenId,
+        old_price: Balance,
+        new_price: Balance,
+    }
+
+    #[ink(event)]
+    pub struct Delisted {
+        #[ink(topic)]
+        id: TokenId,
+    }
+
+    #[ink(event)]
+    pub struct Sold {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        seller: AccountId,
+        #[ink(topic)]
+        buyer: AccountId,
+        price: Balance,
+        artist_share: Balance,
+        seller_proceeds: Balance,
+    }
+
+    #[ink(event)]
+    pub struct AuctionCreated {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        seller: AccountId,
+        reserve: Balance,
+        start: Timestamp,
+        end: Timestamp,
+        min_increment: Balance,
+    }
+
+    #[ink(event)]
+    pub struct BidPlaced {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        bidder: AccountId,
+        amount: Balance,
+        end: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct AuctionSettled {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        winner: Option<AccountId>,
+        price: Balance,
+    }
+
+    #[ink(event)]
+    pub struct SealedAuctionCreated {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        seller: AccountId,
+        reserve: Balance,
+        commit_end: Timestamp,
+        reveal_end: Timestamp,
+        second_price: bool,
+        slash_unrevealed: bool,
+    }
+
+    #[ink(event)]
+    pub struct BidCommitted {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        bidder: AccountId,
+        deposit: Balance,
+    }
+
+    #[ink(event)]
+    pub struct BidRevealed {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        bidder: AccountId,
+        amount: Balance,
+        valid: bool,
+    }
+
+    #[ink(event)]
+    pub struct DepositReleased {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        bidder: AccountId,
+        amount: Balance,
+        slashed: bool,
+    }
+
+    #[ink(event)]
+    pub struct PaymentDeferred {
+        #[ink(topic)]
+        account: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct Withdrawn {
+        #[ink(topic)]
+        account: AccountId,
+        amount: Balance,
+    }
+
+    #[ink(event)]
+    pub struct OfferMade {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        bidder: AccountId,
+        amount: Balance,
+        expiry: Timestamp,
+    }
+
+    #[ink(event)]
+    pub struct OfferCancelled {
+        #[ink(topic)]
+        id: TokenId,
+        #[ink(topic)]
+        bidder: AccountId,
+    }
+
+    #[ink(event)]
+    pub struct AuctionCancelled {
+        #[ink(topic)]
+        id: TokenId,
+    }
+
+    #[ink(event)]
+    pub struct ArtistRegistered {
+        #[ink(topic)]
+        artist_id: ArtistId,
+        #[ink(topic)]
+        account_id: AccountId,
+        name: Vec<u8>,
+    }
+
+    #[ink(event)]
+    pub struct ArtistUpdated {
+        #[ink(topic)]
+        artist_id: ArtistId,
+        #[ink(topic)]
+        account_id: AccountId,
+        name: Vec<u8>,
+    }
+
+    #[ink(event)]
+    pub struct MinterSet {
+        #[ink(topic)]
+        artist_id: ArtistId,
+        #[ink(topic)]
+        minter: AccountId,
+        allowed: bool,
+    }
+
+    #[ink(event)]
+    pub struct RoyaltySet {
+        #[ink(topic)]
+        artist_id: ArtistId,
+        #[ink(topic)]
+        id: Option<TokenId>,
+        royalty: Option<Royalty>,
+    }
+
+    #[ink(event)]
+    pub struct AttributeSet {
+        #[ink(topic)]
+        id: Id,
+        key: Vec<u8>,
+        data: Vec<u8>,
+    }
+
+    #[ink(event)]
+    pub struct BaseUriSet {
+        base_uri: Vec<u8>,
+        suffix: Vec<u8>,
+    }
+
+    #[ink(event)]
+    pub struct MetadataFrozen {}
+
+    impl PSP34 for Contract {
+        /// Transfers `id` to `to`. When `to` is a contract it must acknowledge the
+        /// token through `PSP34Receiver::before_received`, otherwise the transfer is
+        /// reverted. Externally owned accounts are not called.
+        #[ink(message)]
+        fn transfer(&mut self, to: AccountId, id: Id, data: Vec<u8>) -> Result<(), PSP34Error> {
+            let from = self._check_token_exists(&id)?;
+            self._transfer_token(to, id.clone(), data.clone())?;
+            self.check_receiver(from, to, id, data)
+        }
+
+        #[ink(message)]
+        fn approve(&mut self, operator: AccountId, id: Option<Id>, approved: bool) -> Result<(), PSP34Error> {
+            self._approve_for(operator, id.clone(), approved)?;
+
+            // Remember per-token operators so that a transfer can revoke them.
+            if let Some(id) = id {
+                let id = token_id(&id)?;
+                let mut operators = self.token_operators.get(id).unwrap_or_default();
+                operators.retain(|account| *account != operator);
+                if approved {
+                    operators.push(operator);
+                }
+                self.token_operators.insert(id, &operators);
+            }
+            Ok(())
+        }
+    }
+
+    impl PSP34Metadata for Contract {}
+
+    impl PSP34Enumerable for Contract {}
+
+    impl Ownable for Contract {}
+
+    impl metadata::Internal for Contract {
+        fn _emit_attribute_set_event(&self, id: Id, key: Vec<u8>, data: Vec<u8>) {
+            self.env().emit_event(AttributeSet { id, key, data });
+        }
+    }
+
+    impl psp34::Internal for Contract {
+        fn _emit_transfer_event(&self, from: Option<AccountId>, to: Option<AccountId>, id: Id) {
+            self.env().emit_event(Transfer { from, to, id });
+        }
+
+        fn _emit_approval_event(&self, from: AccountId, to: AccountId, id: Option<Id>, approved: bool) {
+            self.env().emit_event(Approval {
+                from,
+                to,
+                id,
+                approved,
+            });
+        }
+    }
+
+    impl psp34::Transfer for Contract {
+        fn _after_token_transfer(
+            &mut self,
+            from: Option<&AccountId>,
+            to: Option<&AccountId>,
+            id: &Id,
+        ) -> Result<(), PSP34Error> {
+            match (from, to) {
+                (Some(from), Some(to)) => {
+                    // A change of ownership revokes the per-token operators and
+                    // invalidates any listing of the previous owner.
+                    self.revoke_token_operators(from, id)?;
+
+                    let id = token_id(id)?;
+                    let mut token = self.tokens.get(id).ok_or(PSP34Error::TokenNotExists)?;
+                    if token.listing.take().is_some() {
+                        self.env().emit_event(Delisted { id });
+                    }
+                    token.owner = *to;
+                    self.tokens.insert(id, &token);
+                }
+                (Some(from), None) => {
+                    // A burn clears everything recorded about the token.
+                    self.revoke_token_operators(from, id)?;
+
+                    let id = token_id(id)?;
+                    let token = self.tokens.get(id).ok_or(PSP34Error::TokenNotExists)?;
+                    if token.listing.is_some() {
+                        self.env().emit_event(Delisted { id });
+                    }
+                    if let Some(content_hash) = self.token_metadata(id).and_then(|metadata| metadata.content_hash) {
+                        self.content_hashes.remove(content_hash);
+                    }
+                    self.write_metadata(id, TokenMetadata::default());
+                    self.remove_artist_token(token.artist, id);
+                    if let Some(position) = self.mint_positions.get(id) {
+                        self.minted_tokens.remove(position);
+                        self.mint_positions.remove(id);
+                    }
+                    self.token_ranges.remove(id);
+                    self.token_royalties.remove(id);
+                    self.tokens.remove(id);
+                }
+                _ => {}
             }
+            Ok(())
+        }
+    }
+
+    /// Maps an OpenBrush `Id` back onto the contract's numeric token id.
+    fn token_id(id: &Id) -> Result<TokenId, PSP34Error> {
+        match id {
+            Id::U32(id) => Ok(*id),
+            _ => Err(PSP34Error::TokenNotExists),
+        }
+    }
+
+    /// Rejects batches that are empty or hold more than `MAX_BATCH_SIZE` items.
+    fn check_batch_size(len: usize) -> Result<(), Error> {
+        if len == 0 || len > MAX_BATCH_SIZE as usize {
+            return Err(Error::InvalidBatchSize)
+        }
+        Ok(())
+    }
+
+    impl Contract {
+        /// Instantiates the contract, capping every royalty at `max_royalty_bps`.
+        #[ink(constructor)]
+        pub fn new(max_royalty_bps: u16) -> Self {
+            assert!(max_royalty_bps <= BASIS_POINTS, "Royalty cap exceeds 100%");
+            let mut instance = Self::default();
+            instance._init_with_owner(Self::env().caller());
+            instance.max_royalty_bps = max_royalty_bps;
+            instance
         }
 
+        /// Mints `id` to the caller on behalf of `artist_id`, who is recorded as its
+        /// creator for good. Only the artist and the minters they authorized may mint.
         #[ink(message)]
-        pub fn mint(&mut self, id: TokenId, price: Balance, artist_id: ArtistId) {
+        pub fn mint(
+            &mut self,
+            id: TokenId,
+            price: Balance,
+            artist_id: ArtistId,
+            metadata: TokenMetadata,
+        ) -> Result<(), Error> {
             let caller = self.env().caller();
-            let token = Token::Owned {
-                price,
+            if !self.can_mint(artist_id, caller)? {
+                return Err(Error::NotArtist)
+            }
+            self.mint_token(caller, id, price, artist_id, metadata)?;
+            self.env().emit_event(Minted {
+                id,
+                artist_id,
                 owner: caller,
-            };
-            self.tokens.insert(id, token);
-            self.set_token_artist(id, artist_id);
+                price,
+            });
+            if price > 0 {
+                self.env().emit_event(Listed {
+                    id,
+                    seller: caller,
+                    price,
+                    expiry: None,
+                });
+            }
+            Ok(())
         }
 
+        /// Mints a series of tokens under the artist id of the caller, all or none.
+        /// Each item holds the token id, its metadata and its asking price.
         #[ink(message)]
-        pub fn transfer(&mut self, id: TokenId, to: AccountId) {
+        pub fn mint_batch(&mut self, items: Vec<(TokenId, TokenMetadata, Balance)>) -> Result<(), Error> {
             let caller = self.env().caller();
-            let token = self.tokens.get(&id).unwrap();
+            let artist_id = self.artist_id_of(caller).ok_or(Error::NotArtist)?;
+            check_batch_size(items.len())?;
 
-            match token {
-                Token::Owned { owner, .. } if *owner == caller => {
-                    self.tokens.insert(id, Token::Owned {
-                        price: 0,
-                        owner: to,
+            let mut ids = Vec::with_capacity(items.len());
+            for (id, metadata, price) in items {
+                self.mint_token(caller, id, price, artist_id, metadata)?;
+                if price > 0 {
+                    self.env().emit_event(Listed {
+                        id,
+                        seller: caller,
+                        price,
+                        expiry: None,
                     });
                 }
-                _ => ink_env::debug_println!("Transfer not allowed"),
+                ids.push(id);
             }
+            self.env().emit_event(BatchMinted {
+                artist_id,
+                owner: caller,
+                ids,
+            });
+            Ok(())
         }
 
+        /// Mints `count` consecutive tokens from `start_id` under the artist id of the
+        /// caller, all or none. Unless empty, `base_uri` replaces the collection base
+        /// URI for the tokens of the range. Each range gets its own never reused id, so
+        /// a later range cannot change the URIs of an earlier one.
         #[ink(message)]
-        pub fn set_price(&mut self, id: TokenId, price: Balance) {
+        pub fn mint_range(&mut self, start_id: TokenId, count: u32, base_uri: Vec<u8>) -> Result<(), Error> {
             let caller = self.env().caller();
-            let token = self.tokens.get(&id).unwrap();
+            let artist_id = self.artist_id_of(caller).ok_or(Error::NotArtist)?;
+            check_batch_size(count as usize)?;
+            let end_id = start_id.checked_add(count).ok_or(Error::InvalidBatchSize)?;
 
-            match token {
-                Token::Owned { owner, .. } if *owner == caller => {
-                    self.tokens.insert(id, Token::Owned {
-                        price,
-                        owner: *owner,
-                    });
+            let range_id = (!base_uri.is_empty()).then(|| {
+                let range_id = self.next_range_id.get().unwrap_or_default();
+                self.next_range_id.set(&(range_id + 1));
+                self.range_base_uris.insert(range_id, &base_uri);
+                range_id
+            });
+            for id in start_id..end_id {
+                self.mint_token(caller, id, 0, artist_id, TokenMetadata::default())?;
+                if let Some(range_id) = range_id {
+                    self.token_ranges.insert(id, &range_id);
                 }
-                _ => ink_env::debug_println!("Set price not allowed"),
             }
+            self.env().emit_event(BatchMinted {
+                artist_id,
+                owner: caller,
+                ids: (start_id..end_id).collect(),
+            });
+            Ok(())
         }
 
+        /// Lists `id` for sale at `price` on behalf of its owner, until `expiry` if
+        /// given. Callable by the owner or an approved operator.
         #[ink(message)]
-        pub fn buy(&mut self, id: TokenId) {
-            let caller = self.env().caller();
-            let token = self.tokens.get(&id).unwrap();
+        pub fn list(&mut self, id: TokenId, price: Balance, expiry: Option<Timestamp>) -> Result<(), Error> {
+            let mut token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            self.ensure_owner_or_approved(&token, id)?;
+            if self.active_listing(&token).is_some() {
+                return Err(Error::AlreadyListed)
+            }
+            if price == 0 {
+                return Err(Error::InvalidPrice)
+            }
+            if expiry.map_or(false, |expiry| expiry <= self.env().block_timestamp()) {
+                return Err(Error::InvalidExpiry)
+            }
+
+            let seller = token.owner;
+            token.listing = Some(Listing {
+                price,
+                seller,
+                expiry,
+                decay: None,
+            });
+            self.tokens.insert(id, &token);
+            self.env().emit_event(Listed {
+                id,
+                seller,
+                price,
+                expiry,
+            });
+            Ok(())
+        }
 
-            match token {
-                Token::ForSale { price, artist } => {
-                    let artist_account = self.artist_account(*artist);
+        /// Lists `id` as a Dutch auction. The price falls from `start_price` now to
+        /// `floor_price` at `end`, continuously when `step` is zero or every `step`
+        /// milliseconds otherwise, and stays at the floor until the token is bought or
+        /// delisted.
+        #[ink(message)]
+        pub fn list_dutch(
+            &mut self,
+            id: TokenId,
+            start_price: Balance,
+            floor_price: Balance,
+            end: Timestamp,
+            step: Timestamp,
+        ) -> Result<(), Error> {
+            let mut token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            self.ensure_owner_or_approved(&token, id)?;
+            if self.active_listing(&token).is_some() {
+                return Err(Error::AlreadyListed)
+            }
+            if floor_price == 0 || start_price < floor_price {
+                return Err(Error::InvalidPrice)
+            }
+            let start = self.env().block_timestamp();
+            if end <= start {
+                return Err(Error::InvalidExpiry)
+            }
 
-                    let balance = self.env().balance();
-                    let value = self.env().transferred_balance();
-                    assert_eq!(value, *price, "Incorrect price");
+            let seller = token.owner;
+            token.listing = Some(Listing {
+                price: start_price,
+                seller,
+                expiry: None,
+                decay: Some(PriceDecay {
+                    floor: floor_price,
+                    start,
+                    end,
+                    step,
+                }),
+            });
+            self.tokens.insert(id, &token);
+            self.env().emit_event(DutchListed {
+                id,
+                seller,
+                start_price,
+                floor_price,
+                start,
+                end,
+                step,
+            });
+            Ok(())
+        }
 
-                    let artist_share = value / 10;
-                    let buyer_share = value - artist_share;
+        /// Changes the price of an active listing. The terms of a Dutch auction are
+        /// changed by delisting it and calling `list_dutch` again.
+        #[ink(message)]
+        pub fn update_listing(&mut self, id: TokenId, new_price: Balance) -> Result<(), Error> {
+            let mut token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            self.ensure_owner_or_approved(&token, id)?;
+            let mut listing = self.checked_listing(&token)?;
+            if listing.decay.is_some() {
+                return Err(Error::DutchListing)
+            }
+            if new_price == 0 {
+                return Err(Error::InvalidPrice)
+            }
 
-                    // Transfer the token to
-                                    // the buyer
-                self.tokens.insert(id, Token::Owned {
-                    price: 0,
-                    owner: caller,
+            let old_price = listing.price;
+            listing.price = new_price;
+            token.listing = Some(listing);
+            self.tokens.insert(id, &token);
+            if old_price != new_price {
+                self.env().emit_event(PriceChanged {
+                    id,
+                    old_price,
+                    new_price,
                 });
+            }
+            Ok(())
+        }
 
-                // Transfer the payment to the artist and the buyer
-                let _ = artist_account
-                    .transfer(artist_share)
-                    .expect("Transfer to artist failed");
-                let _ = caller
-                    .transfer(buyer_share)
-                    .expect("Transfer to buyer failed");
-                }
-            _ => ink_env::debug_println!("Buy not allowed"),
+        /// Withdraws `id` from sale. Expired listings can be withdrawn too.
+        #[ink(message)]
+        pub fn delist(&mut self, id: TokenId) -> Result<(), Error> {
+            let mut token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            self.ensure_owner_or_approved(&token, id)?;
+            if token.listing.take().is_none() {
+                return Err(Error::NotForSale)
             }
+
+            self.tokens.insert(id, &token);
+            self.env().emit_event(Delisted { id });
+            Ok(())
         }
 
+        /// Sets the asking price of `id`: lists it without expiry, updates the price of
+        /// its active listing, or delists it when `price` is zero. Dutch auctions are
+        /// left alone.
         #[ink(message)]
-        pub fn set_artist(&mut self, id: ArtistId, name: Vec<u8>, account_id: AccountId) {
+        pub fn set_price(&mut self, id: TokenId, price: Balance) -> Result<(), Error> {
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            if price == 0 && token.listing.is_none() {
+                return self.ensure_owner_or_approved(&token, id)
+            }
+            match (price, self.active_listing(&token)) {
+                (_, Some(Listing { decay: Some(_), .. })) => {
+                    self.ensure_owner_or_approved(&token, id)?;
+                    Err(Error::DutchListing)
+                }
+                (0, _) => self.delist(id),
+                (_, Some(_)) => self.update_listing(id, price),
+                (_, None) => self.list(id, price, None),
+            }
+        }
+
+        /// Buys a listed token at its asking price, or at the current price of a Dutch
+        /// auction.
+        ///
+        /// The payment is escrowed by the call itself: the seller and the artist are paid
+        /// out of the transferred value and any overpayment is refunded to the buyer. A
+        /// failed payout returns an error, which reverts the whole purchase.
+        #[ink(message, payable)]
+        pub fn buy(&mut self, id: TokenId) -> Result<(), Error> {
             let caller = self.env().caller();
-            assert!(caller == account_id, "Only the artist can set their details");
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            let listing = self.checked_listing(&token)?;
+
+            let price = self.listing_price(&listing);
+            let value = self.env().transferred_value();
+            if value < price {
+                return Err(Error::WrongPrice)
+            }
+            self.complete_sale(id, listing.seller, listing.seller, caller, price, false)?;
+
+            // Refund any overpayment to the buyer
+            self.pay(caller, value - price)
+        }
+
+        /// Returns the price `id` can be bought at right now.
+        #[ink(message)]
+        pub fn current_price(&self, id: TokenId) -> Result<Balance, Error> {
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            let listing = self.checked_listing(&token)?;
+            Ok(self.listing_price(&listing))
+        }
+
+        /// Offers the transferred value for `id` until `expiry`, whether or not the
+        /// token is listed. The offer is escrowed by the contract and replaces, and
+        /// refunds, any earlier offer of the caller on the token.
+        #[ink(message, payable)]
+        pub fn make_offer(&mut self, id: TokenId, expiry: Timestamp) -> Result<(), Error> {
+            let mint_position = self.mint_positions.get(id).ok_or(Error::TokenNotFound)?;
+            let amount = self.env().transferred_value();
+            if amount == 0 {
+                return Err(Error::InvalidPrice)
+            }
+            if expiry <= self.env().block_timestamp() {
+                return Err(Error::InvalidExpiry)
+            }
 
-            let artist = Artist { name, account_id };
-            self.artists.insert(id, artist);
+            let bidder = self.env().caller();
+            if let Some(previous) = self.offers.get((id, bidder)) {
+                self.pay(bidder, previous.amount)?;
+            }
+            self.offers.insert(
+                (id, bidder),
+                &Offer {
+                    amount,
+                    expiry,
+                    mint_position,
+                },
+            );
+            self.env().emit_event(OfferMade {
+                id,
+                bidder,
+                amount,
+                expiry,
+            });
+            Ok(())
         }
 
+        /// Withdraws the caller's offer on `id` and refunds it. Expired offers are
+        /// reclaimed the same way.
         #[ink(message)]
-        pub fn artist_account(&self, id: ArtistId) -> AccountId {
-            let artist = self.artists.get(&id).unwrap();
-            artist.account_id
+        pub fn cancel_offer(&mut self, id: TokenId) -> Result<(), Error> {
+            let bidder = self.env().caller();
+            let offer = self.offers.get((id, bidder)).ok_or(Error::OfferNotFound)?;
+
+            self.offers.remove((id, bidder));
+            self.pay(bidder, offer.amount)?;
+            self.env().emit_event(OfferCancelled { id, bidder });
+            Ok(())
         }
 
+        /// Accepts the offer of `bidder` on `id`, selling the token to them for the
+        /// escrowed amount. The royalties and the seller are paid like a sale through
+        /// `buy`. Offers made before the token was burned and reminted cannot be
+        /// accepted. Callable by the owner or an approved operator.
         #[ink(message)]
-        pub fn get_token(&self, id: TokenId) -> Option<Token> {
-            self.tokens.get(&id).cloned()
+        pub fn accept_offer(&mut self, id: TokenId, bidder: AccountId) -> Result<(), Error> {
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            self.ensure_owner_or_approved(&token, id)?;
+            let offer = self
+                .offers
+                .get((id, bidder))
+                .filter(|offer| self.mint_positions.get(id) == Some(offer.mint_position))
+                .ok_or(Error::OfferNotFound)?;
+            if self.env().block_timestamp() >= offer.expiry {
+                return Err(Error::OfferExpired)
+            }
+
+            self.offers.remove((id, bidder));
+            self.complete_sale(id, token.owner, token.owner, bidder, offer.amount, false)
+        }
+
+        #[ink(message)]
+        pub fn get_offer(&self, id: TokenId, bidder: AccountId) -> Option<Offer> {
+            self.offers.get((id, bidder))
+        }
+
+        /// Pays out the sale proceeds, royalties and refunds owed to the caller whose
+        /// transfer failed when they were due.
+        #[ink(message)]
+        pub fn withdraw(&mut self) -> Result<(), Error> {
+            let account = self.env().caller();
+            let amount = self.pending_payments.get(account).ok_or(Error::NothingToWithdraw)?;
+
+            self.pay(account, amount)?;
+            self.pending_payments.remove(account);
+            self.env().emit_event(Withdrawn { account, amount });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn pending_payment(&self, account: AccountId) -> Balance {
+            self.pending_payments.get(account).unwrap_or_default()
+        }
+
+        /// Puts `id` up for a timed English auction running from `start` to `end`. The
+        /// token is held in custody by the contract until the auction is settled or
+        /// cancelled. Callable by the owner or an approved operator.
+        #[ink(message)]
+        pub fn create_auction(
+            &mut self,
+            id: TokenId,
+            reserve: Balance,
+            start: Timestamp,
+            end: Timestamp,
+            min_increment: Balance,
+        ) -> Result<(), Error> {
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            self.ensure_owner_or_approved(&token, id)?;
+            if end <= start || end <= self.env().block_timestamp() {
+                return Err(Error::InvalidAuction)
+            }
+
+            let seller = token.owner;
+            self.move_token(seller, self.env().account_id(), id)?;
+            self.auctions.insert(
+                id,
+                &Auction {
+                    seller,
+                    reserve,
+                    start,
+                    end,
+                    min_increment,
+                    highest_bid: None,
+                },
+            );
+            self.env().emit_event(AuctionCreated {
+                id,
+                seller,
+                reserve,
+                start,
+                end,
+                min_increment,
+            });
+            Ok(())
+        }
+
+        /// Bids the transferred value on the auction of `id`. The bid is escrowed by
+        /// the contract and the previous highest bidder is refunded. A bid landing
+        /// within `AUCTION_EXTENSION_WINDOW` of the end extends the auction.
+        #[ink(message, payable)]
+        pub fn bid(&mut self, id: TokenId) -> Result<(), Error> {
+            let mut auction = self.auctions.get(id).ok_or(Error::AuctionNotFound)?;
+            let now = self.env().block_timestamp();
+            if now < auction.start {
+                return Err(Error::AuctionNotStarted)
+            }
+            if now >= auction.end {
+                return Err(Error::AuctionEnded)
+            }
+
+            let bidder = self.env().caller();
+            let amount = self.env().transferred_value();
+            let minimum = match auction.highest_bid {
+                Some((_, highest)) => highest.saturating_add(auction.min_increment.max(1)),
+                None => auction.reserve,
+            };
+            if amount < minimum {
+                return Err(Error::BidTooLow)
+            }
+
+            if let Some((previous_bidder, previous_amount)) = auction.highest_bid.replace((bidder, amount)) {
+                self.pay_or_defer(previous_bidder, previous_amount);
+            }
+            auction.end = auction.end.max(now + AUCTION_EXTENSION_WINDOW);
+            self.auctions.insert(id, &auction);
+            self.env().emit_event(BidPlaced {
+                id,
+                bidder,
+                amount,
+                end: auction.end,
+            });
+            Ok(())
         }
 
+        /// Closes the auction of `id` once it has ended. The token goes to the highest
+        /// bidder, whose bid pays the royalties and the seller like a sale through
+        /// `buy`, or back to the seller when there was no bid. Callable by anyone.
         #[ink(message)]
-        pub fn set_token_artist(&mut self, id: TokenId, artist_id: ArtistId) {
-            let token = self.tokens.get(&id).unwrap();
-            match token {
-                Token::Owned { .. } => {
-                    ink_env::debug_println!("Only for sale tokens can be associated with an artist");
+        pub fn settle_auction(&mut self, id: TokenId) -> Result<(), Error> {
+            let auction = self.auctions.get(id).ok_or(Error::AuctionNotFound)?;
+            if self.env().block_timestamp() < auction.end {
+                return Err(Error::AuctionNotEnded)
+            }
+
+            self.auctions.remove(id);
+            let custody = self.env().account_id();
+            match auction.highest_bid {
+                Some((winner, price)) => {
+                    self.complete_sale(id, custody, auction.seller, winner, price, true)?;
+                    self.env().emit_event(AuctionSettled {
+                        id,
+                        winner: Some(winner),
+                        price,
+                    });
                 }
-                Token::ForSale { price, .. } => {
-                    self.tokens.insert(id, Token::ForSale {
-                        price: *price,
-                        artist: artist_id,
+                None => {
+                    self.move_token(custody, auction.seller, id)?;
+                    self.env().emit_event(AuctionSettled {
+                        id,
+                        winner: None,
+                        price: 0,
                     });
                 }
             }
+            Ok(())
         }
 
+        /// Cancels the auction of `id` and returns the token to the seller. Only the
+        /// seller or an operator approved for all their tokens can cancel, since
+        /// per-token approvals are revoked when the token goes into custody, and only
+        /// before the first bid.
         #[ink(message)]
-        pub fn next_artist_id(&self) -> ArtistId {
-            *self.next_artist_id
+        pub fn cancel_auction(&mut self, id: TokenId) -> Result<(), Error> {
+            let auction = self.auctions.get(id).ok_or(Error::AuctionNotFound)?;
+            self.ensure_acting_for(auction.seller, id)?;
+            if auction.highest_bid.is_some() {
+                return Err(Error::AuctionHasBids)
+            }
+
+            self.auctions.remove(id);
+            self.move_token(self.env().account_id(), auction.seller, id)?;
+            self.env().emit_event(AuctionCancelled { id });
+            Ok(())
         }
 
         #[ink(message)]
-        pub fn increment_artist_id(&mut self) {
-            *self.next_artist_id += 1;
+        pub fn get_auction(&self, id: TokenId) -> Option<Auction> {
+            self.auctions.get(id)
         }
-    }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        /// Puts `id` up for a sealed-bid auction. Bidders commit to a hidden bid until
+        /// `commit_end` and reveal it until `reveal_end`. The highest valid bid wins and
+        /// pays either its own amount or, with `second_price`, the second highest bid
+        /// (at least the reserve). Deposits of bidders who never reveal go to the seller
+        /// when `slash_unrevealed` is set and back to the bidder otherwise. Callable by
+        /// the owner or an approved operator.
+        #[ink(message)]
+        pub fn create_sealed_auction(
+            &mut self,
+            id: TokenId,
+            reserve: Balance,
+            commit_end: Timestamp,
+            reveal_end: Timestamp,
+            second_price: bool,
+            slash_unrevealed: bool,
+        ) -> Result<(), Error> {
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            self.ensure_owner_or_approved(&token, id)?;
+            if commit_end <= self.env().block_timestamp() || reveal_end <= commit_end {
+                return Err(Error::InvalidAuction)
+            }
 
-    #[test]
-    fn test_mint() {
-        let mut psp34 = PSP34::new();
-        let caller = account_id::<ink_env::DefaultEnvironment>()
-            .unwrap_or(Default::default());
-
-        psp34.mint(0, 100, 0);
-        let token = psp34.get_token(0).unwrap();
-        assert_eq!(token, Token::Owned {
-            price: 100,
-            owner: caller,
-        });
-    }
-
-    #[test]
-    fn test_transfer() {
-        let mut psp34 = PSP34::new();
-        let caller1 = account_id::<ink_env::DefaultEnvironment>()
-            .unwrap_or(Default::default());
-        let caller2 = AccountId::from([0x2; 32]);
-
-        psp34.mint(0, 100, 0);
-
-        // Try to transfer token to another account
-        psp34.transfer(0, caller2);
-        let token = psp34.get_token(0).unwrap();
-        assert_eq!(token, Token::Owned {
-            price: 0,
-            owner: caller2,
-        });
-
-        // Try to transfer token from another account (should fail)
-        psp34.env().test_set_caller(caller2);
-        psp34.transfer(0, caller1);
-        let token = psp34.get_token(0).unwrap();
-        assert_eq!(token, Token::Owned {
-            price: 0,
-            owner: caller2,
-        });
-    }
-
-    #[test]
-    fn test_set_artist() {
-        let mut psp34 = PSP34::new();
-        let caller = account_id::<ink_env::DefaultEnvironment>()
-            .unwrap_or(Default::default());
-
-        // Set artist details
-        psp34.set_artist(0, b"Artist 1".to_vec(), caller);
-
-        // Check artist details
-        let artist_id = psp34.next_artist_id();
-        assert_eq!(psp34.artist_account(artist_id - 1), caller);
-    }
-    
-
-    #[test]
-    fn test_buy() {
-        let mut psp34 = PSP34::new();
-        let caller1 = account_id::<ink_env::DefaultEnvironment>()
-            .unwrap_or(Default::default());
-        let caller2 = AccountId::from([0x2; 32]);
-
-        // Mint a token and set it for sale
-        psp34.mint(0, 100, 0);
-        psp34.set_token_artist(0, 0);
-        psp34.set_for_sale(0, 50);
-
-        // Try to buy token as owner (should fail)
-        psp34.buy(0);
-        let token = psp34.get_token(0).unwrap();
-        assert_eq!(token, Token::ForSale {
-            price: 50,
-            artist: 0,
-        });
-
-        // Try to buy token as non-owner
-        psp34.env().test_set_caller(caller2);
-        psp34.buy(0);
-        let token = psp34.get_token(0).unwrap();
-        assert_eq!(token, Token::Owned {
-            price: 0,
-            owner: caller2,
-        });
-    }
-}
+            let seller = token.owner;
+            self.move_token(seller, self.env().account_id(), id)?;
+            self.sealed_auctions.insert(
+                id,
+                &SealedAuction {
+                    seller,
+                    reserve,
+                    commit_end,
+                    reveal_end,
+                    second_price,
+                    slash_unrevealed,
+                    highest_bid: None,
+                    second_bid: 0,
+                },
+            );
+            self.env().emit_event(SealedAuctionCreated {
+                id,
+                seller,
+                reserve,
+                commit_end,
+                reveal_end,
+                second_price,
+                slash_unrevealed,
+            });
+            Ok(())
+        }
+
+        /// Commits to a sealed bid on `id`, where `commitment` is the
+        /// `sealed_bid_commitment` of the token, the caller, the bid and a secret salt.
+        /// Binding the caller keeps others from replaying the commitment as their own.
+        /// The transferred value is escrowed as the deposit and must cover the bid;
+        /// any excess is refunded on reveal.
+        #[ink(message, payable)]
+        pub fn commit_bid(&mut self, id: TokenId, commitment: Hash) -> Result<(), Error> {
+            let auction = self.sealed_auctions.get(id).ok_or(Error::AuctionNotFound)?;
+            if self.env().block_timestamp() >= auction.commit_end {
+                return Err(Error::WrongPhase)
+            }
+            let bidder = self.env().caller();
+            if self.sealed_bids.contains((id, bidder)) {
+                return Err(Error::AlreadyCommitted)
+            }
+
+            let deposit = self.env().transferred_value();
+            self.sealed_bids.insert(
+                (id, bidder),
+                &SealedBid {
+                    commitment,
+                    deposit,
+                    seller: auction.seller,
+                    reveal_end: auction.reveal_end,
+                    slash_unrevealed: auction.slash_unrevealed,
+                },
+            );
+            self.env().emit_event(BidCommitted { id, bidder, deposit });
+            Ok(())
+        }
+
+        /// Reveals the caller's sealed bid on `id`. A bid that is below the reserve or
+        /// not covered by the deposit is refunded, as is the deposit of any bid that is
+        /// outbid. The highest bid stays escrowed until the auction is settled.
+        #[ink(message)]
+        pub fn reveal_bid(&mut self, id: TokenId, amount: Balance, salt: Hash) -> Result<(), Error> {
+            let mut auction = self.sealed_auctions.get(id).ok_or(Error::AuctionNotFound)?;
+            let now = self.env().block_timestamp();
+            if now < auction.commit_end || now >= auction.reveal_end {
+                return Err(Error::WrongPhase)
+            }
+            let bidder = self.env().caller();
+            let bid = self.sealed_bids.get((id, bidder)).ok_or(Error::BidNotFound)?;
+            if bid.reveal_end != auction.reveal_end
+                || bid.commitment != self.sealed_bid_commitment(id, bidder, amount, salt)
+            {
+                return Err(Error::InvalidReveal)
+            }
+
+            self.sealed_bids.remove((id, bidder));
+            let valid = amount >= auction.reserve && amount <= bid.deposit;
+            let mut refund = bid.deposit;
+            if valid {
+                match auction.highest_bid {
+                    Some((_, highest)) if amount <= highest => {
+                        auction.second_bid = auction.second_bid.max(amount);
+                    }
+                    previous => {
+                        if let Some((previous_bidder, previous_amount)) = previous {
+                            auction.second_bid = previous_amount;
+                            self.pay_or_defer(previous_bidder, previous_amount);
+                        }
+                        auction.highest_bid = Some((bidder, amount));
+                        refund -= amount;
+                    }
+                }
+                self.sealed_auctions.insert(id, &auction);
+            }
+            self.pay(bidder, refund)?;
+            self.env().emit_event(BidRevealed {
+                id,
+                bidder,
+                amount,
+                valid,
+            });
+            Ok(())
+        }
 
+        /// Closes the sealed-bid auction of `id` once the reveal phase is over. The
+        /// token goes to the highest valid bidder, who pays the winning price like a
+        /// sale through `buy` and gets the rest of their bid back, or returns to the
+        /// seller when no valid bid was revealed. Callable by anyone.
+        #[ink(message)]
+        pub fn settle_sealed_auction(&mut self, id: TokenId) -> Result<(), Error> {
+            let auction = self.sealed_auctions.get(id).ok_or(Error::AuctionNotFound)?;
+            if self.env().block_timestamp() < auction.reveal_end {
+                return Err(Error::AuctionNotEnded)
+            }
+
+            self.sealed_auctions.remove(id);
+            let custody = self.env().account_id();
+            match auction.highest_bid {
+                Some((winner, amount)) => {
+                    let price = if auction.second_price {
+                        auction.second_bid.max(auction.reserve)
+                    } else {
+                        amount
+                    };
+                    self.complete_sale(id, custody, auction.seller, winner, price, true)?;
+                    self.pay_or_defer(winner, amount - price);
+                    self.env().emit_event(AuctionSettled {
+                        id,
+                        winner: Some(winner),
+                        price,
+                    });
+                }
+                None => {
+                    self.move_token(custody, auction.seller, id)?;
+                    self.env().emit_event(AuctionSettled {
+                        id,
+                        winner: None,
+                        price: 0,
+                    });
+                }
+            }
+            Ok(())
+        }
+
+        /// Releases the deposit of a bid on `id` that `bidder` never revealed, once the
+        /// reveal phase is over. The deposit goes back to the bidder, or to the seller
+        /// when the auction slashes unrevealed bids. Callable by anyone.
+        #[ink(message)]
+        pub fn withdraw_unrevealed(&mut self, id: TokenId, bidder: AccountId) -> Result<(), Error> {
+            let bid = self.sealed_bids.get((id, bidder)).ok_or(Error::BidNotFound)?;
+            if self.env().block_timestamp() < bid.reveal_end {
+                return Err(Error::WrongPhase)
+            }
+
+            self.sealed_bids.remove((id, bidder));
+            let recipient = if bid.slash_unrevealed { bid.seller } else { bidder };
+            self.pay_or_defer(recipient, bid.deposit);
+            self.env().emit_event(DepositReleased {
+                id,
+                bidder,
+                amount: bid.deposit,
+                slashed: bid.slash_unrevealed,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_sealed_auction(&self, id: TokenId) -> Option<SealedAuction> {
+            self.sealed_auctions.get(id)
+        }
+
+        /// Returns the commitment `bidder` submits to `commit_bid` for a bid of `amount`
+        /// on `id`, hidden by `salt`.
+        #[ink(message)]
+        pub fn sealed_bid_commitment(&self, id: TokenId, bidder: AccountId, amount: Balance, salt: Hash) -> Hash {
+            Hash::from(self.env().hash_encoded::<Blake2x256, _>(&(id, bidder, amount, salt)))
+        }
+
+        /// Registers the caller as an artist under the next free artist id.
+        #[ink(message)]
+        pub fn register_artist(&mut self, name: Vec<u8>) -> Result<ArtistId, Error> {
+            let account_id = self.env().caller();
+            if self.artist_ids.contains(account_id) {
+                return Err(Error::AlreadyRegistered)
+            }
+
+            let id = self.next_artist_id();
+            self.next_artist_id.set(&(id + 1));
+            self.artist_ids.insert(account_id, &id);
+            self.artists.insert(
+                id,
+                &Artist {
+                    name: name.clone(),
+                    account_id,
+                },
+            );
+            self.env().emit_event(ArtistRegistered {
+                artist_id: id,
+                account_id,
+                name,
+            });
+            Ok(id)
+        }
+
+        /// Updates the profile of an artist. Only the registered account may do so.
+        #[ink(message)]
+        pub fn update_artist(&mut self, id: ArtistId, name: Vec<u8>) -> Result<(), Error> {
+            let mut artist = self.artists.get(id).ok_or(Error::ArtistNotFound)?;
+            if artist.account_id != self.env().caller() {
+                return Err(Error::NotArtist)
+            }
+
+            artist.name = name.clone();
+            self.artists.insert(id, &artist);
+            self.env().emit_event(ArtistUpdated {
+                artist_id: id,
+                account_id: artist.account_id,
+                name,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn get_artist(&self, id: ArtistId) -> Option<Artist> {
+            self.artists.get(id)
+        }
+
+        #[ink(message)]
+        pub fn artist_id_of(&self, account_id: AccountId) -> Option<ArtistId> {
+            self.artist_ids.get(account_id)
+        }
+
+        #[ink(message)]
+        pub fn artist_account(&self, id: ArtistId) -> Result<AccountId, Error> {
+            let artist = self.artists.get(id).ok_or(Error::ArtistNotFound)?;
+            Ok(artist.account_id)
+        }
+
+        #[ink(message)]
+        pub fn get_token(&self, id: TokenId) -> Option<Token> {
+            self.tokens.get(id)
+        }
+
+        /// Transfers several tokens, all or none. On failure the error is returned
+        /// along with the index of the offending item, or `None` when the batch itself
+        /// is invalid.
+        #[ink(message)]
+        pub fn transfer_batch(&mut self, items: Vec<(TokenId, AccountId)>) -> Result<(), (Option<u32>, Error)> {
+            check_batch_size(items.len()).map_err(|error| (None, error))?;
+            for (index, (id, to)) in items.into_iter().enumerate() {
+                let id = Id::U32(id);
+                self._check_token_exists(&id)
+                    .and_then(|from| {
+                        self._transfer_token(to, id.clone(), Vec::new())?;
+                        self.check_receiver(from, to, id, Vec::new())
+                    })
+                    .map_err(|error| (Some(index as u32), error.into()))?;
+            }
+            Ok(())
+        }
+
+        /// Sets the asking price of several tokens, all or none, with the same rules
+        /// as `set_price`. On failure the error is returned along with the index of
+        /// the offending item, or `None` when the batch itself is invalid.
+        #[ink(message)]
+        pub fn list_batch(&mut self, items: Vec<(TokenId, Balance)>) -> Result<(), (Option<u32>, Error)> {
+            check_batch_size(items.len()).map_err(|error| (None, error))?;
+            for (index, (id, price)) in items.into_iter().enumerate() {
+                self.set_price(id, price).map_err(|error| (Some(index as u32), error))?;
+            }
+            Ok(())
+        }
+
+        /// Destroys `id`. Callable by the owner of the token or an approved operator.
+        #[ink(message)]
+        pub fn burn(&mut self, id: TokenId) -> Result<(), Error> {
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            self.ensure_owner_or_approved(&token, id)?;
+
+            self._burn_from(token.owner, Id::U32(id))?;
+            Ok(())
+        }
+
+        /// Lets the artist destroy one of their tokens that was never sold, i.e. that
+        /// is still held by the artist or one of their minters.
+        #[ink(message)]
+        pub fn burn_unsold(&mut self, id: TokenId) -> Result<(), Error> {
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            if self.artist_account(token.artist)? != self.env().caller() {
+                return Err(Error::NotArtist)
+            }
+            if !self.can_mint(token.artist, token.owner)? {
+                return Err(Error::TokenSold)
+            }
+
+            self._burn_from(token.owner, Id::U32(id))?;
+            Ok(())
+        }
+
+        /// Allows or revokes `minter` to mint tokens on behalf of the calling artist.
+        #[ink(message)]
+        pub fn set_minter(&mut self, artist_id: ArtistId, minter: AccountId, allowed: bool) -> Result<(), Error> {
+            if self.artist_account(artist_id)? != self.env().caller() {
+                return Err(Error::NotArtist)
+            }
+
+            if allowed {
+                self.minters.insert((artist_id, minter), &());
+            } else {
+                self.minters.remove((artist_id, minter));
+            }
+            self.env().emit_event(MinterSet {
+                artist_id,
+                minter,
+                allowed,
+            });
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn can_mint(&self, artist_id: ArtistId, account_id: AccountId) -> Result<bool, Error> {
+            let artist_account = self.artist_account(artist_id)?;
+            Ok(artist_account == account_id || self.minters.contains((artist_id, account_id)))
+        }
+
+        /// Replaces the metadata of `id`. Only the artist can do so, and only while the
+        /// metadata is mutable; passing immutable metadata freezes it for good.
+        #[ink(message)]
+        pub fn update_metadata(&mut self, id: TokenId, metadata: TokenMetadata) -> Result<(), Error> {
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            if self.artist_account(token.artist)? != self.env().caller() {
+                return Err(Error::NotArtist)
+            }
+            if self.metadata_frozen || !self.mutable_metadata.contains(id) {
+                return Err(Error::MetadataFrozen)
+            }
+
+            let old_hash = self.token_metadata(id).and_then(|metadata| metadata.content_hash);
+            self.register_content_hash(id, old_hash, metadata.content_hash)?;
+            self.write_metadata(id, metadata);
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn token_metadata(&self, id: TokenId) -> Option<TokenMetadata> {
+            if !self.tokens.contains(id) {
+                return None
+            }
+            let attribute = |key: &[u8]| self.get_attribute(Id::U32(id), key.to_vec()).unwrap_or_default();
+            let content_hash = <[u8; 32]>::try_from(attribute(CONTENT_HASH_KEY).as_slice())
+                .ok()
+                .map(Hash::from);
+            Some(TokenMetadata {
+                name: attribute(NAME_KEY),
+                description: attribute(DESCRIPTION_KEY),
+                uri: attribute(URI_KEY),
+                content_hash,
+                mutable: self.mutable_metadata.contains(id),
+            })
+        }
+
+        /// Returns the token minted with the artwork of the given content hash, so
+        /// buyers can check the provenance of a piece.
+        #[ink(message)]
+        pub fn token_by_content_hash(&self, content_hash: Hash) -> Option<TokenId> {
+            self.content_hashes.get(content_hash)
+        }
+
+        /// Sets an attribute of the whole collection, readable through
+        /// `PSP34Metadata::get_attribute` with the collection id.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_collection_attribute(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<(), Error> {
+            if self.metadata_frozen {
+                return Err(Error::MetadataFrozen)
+            }
+            let collection_id = self.collection_id();
+            self._set_attribute(collection_id, key, value);
+            Ok(())
+        }
+
+        /// Sets the URI prefix and suffix used for tokens without their own URI.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn set_base_uri(&mut self, base_uri: Vec<u8>, suffix: Vec<u8>) -> Result<(), Error> {
+            if self.metadata_frozen {
+                return Err(Error::MetadataFrozen)
+            }
+
+            self.base_uri.set(&base_uri);
+            self.uri_suffix.set(&suffix);
+            self.env().emit_event(BaseUriSet { base_uri, suffix });
+            Ok(())
+        }
+
+        /// Permanently freezes the base URI, the collection attributes and the
+        /// metadata of every token.
+        #[ink(message)]
+        #[modifiers(only_owner)]
+        pub fn freeze_metadata(&mut self) -> Result<(), Error> {
+            if self.metadata_frozen {
+                return Err(Error::MetadataFrozen)
+            }
+
+            self.metadata_frozen = true;
+            self.env().emit_event(MetadataFrozen {});
+            Ok(())
+        }
+
+        #[ink(message)]
+        pub fn metadata_frozen(&self) -> bool {
+            self.metadata_frozen
+        }
+
+        /// Returns the URI of `id`: its own URI when one was given at mint time,
+        /// otherwise `base_uri + id + suffix`, using the base URI of the range the
+        /// token was minted in, if any.
+        #[ink(message)]
+        pub fn token_uri(&self, id: TokenId) -> Option<Vec<u8>> {
+            if !self.tokens.contains(id) {
+                return None
+            }
+            if let Some(uri) = self.get_attribute(Id::U32(id), URI_KEY.to_vec()) {
+                if !uri.is_empty() {
+                    return Some(uri)
+                }
+            }
+
+            let mut uri = match self.token_ranges.get(id) {
+                Some(range_id) => self.range_base_uris.get(range_id)?,
+                None => self.base_uri.get()?,
+            };
+            uri.extend_from_slice(id.to_string().as_bytes());
+            uri.extend(self.uri_suffix.get().unwrap_or_default());
+            Some(uri)
+        }
+
+        /// Pages through all tokens in minting order. The cursor is a position in the
+        /// append-only log of mints, so burns and mints between calls never shift it.
+        /// Each call inspects at most `MAX_PAGE_SCAN` positions, so a page may hold
+        /// fewer items than `limit` while still returning a cursor.
+        #[ink(message)]
+        pub fn list_tokens(&self, start: u32, limit: u32) -> Page<(TokenId, Token)> {
+            self.scan_tokens(start, limit.min(MAX_PAGE_SIZE), |_| true)
+        }
+
+        /// Pages through the tokens with a valid listing, in minting order and with the
+        /// same cursor as `list_tokens`.
+        #[ink(message)]
+        pub fn list_for_sale(&self, start: u32, limit: u32) -> Page<(TokenId, Token)> {
+            self.scan_tokens(start, limit.min(MAX_PAGE_SIZE), |token| {
+                self.active_listing(token).is_some()
+            })
+        }
+
+        /// Pages through the registered artists in registration order.
+        #[ink(message)]
+        pub fn list_artists(&self, start: ArtistId, limit: u32) -> Page<(ArtistId, Artist)> {
+            let total = self.next_artist_id();
+            let end = start.saturating_add(limit.min(MAX_PAGE_SIZE)).min(total);
+            let items = (start..end)
+                .filter_map(|id| self.artists.get(id).map(|artist| (id, artist)))
+                .collect();
+            Page {
+                items,
+                next: (end < total).then_some(end),
+            }
+        }
+
+        /// Returns the `index`-th token created by `artist_id`.
+        #[ink(message)]
+        pub fn artist_token_by_index(&self, artist_id: ArtistId, index: u32) -> Result<TokenId, Error> {
+            self.artist_tokens
+                .get((artist_id, index))
+                .ok_or(Error::TokenNotFound)
+        }
+
+        #[ink(message)]
+        pub fn artist_token_count(&self, artist_id: ArtistId) -> u32 {
+            self.artist_token_counts.get(artist_id).unwrap_or_default()
+        }
+
+        #[ink(message)]
+        pub fn next_artist_id(&self) -> ArtistId {
+            self.next_artist_id.get().unwrap_or_default()
+        }
+
+        #[ink(message)]
+        pub fn max_royalty_bps(&self) -> u16 {
+            self.max_royalty_bps
+        }
+
+        /// Sets the default royalty applied to every token of the artist.
+        #[ink(message)]
+        pub fn set_artist_royalty(
+            &mut self,
+            artist_id: ArtistId,
+            bps: u16,
+            recipients: Vec<(AccountId, u16)>,
+        ) -> Result<(), Error> {
+            if self.artist_account(artist_id)? != self.env().caller() {
+                return Err(Error::NotArtist)
+            }
+            let royalty = self.royalty(bps, recipients)?;
+
+            self.artist_royalties.insert(artist_id, &royalty);
+            self.env().emit_event(RoyaltySet {
+                artist_id,
+                id: None,
+                royalty: Some(royalty),
+            });
+            Ok(())
+        }
+
+        /// Overrides the artist royalty for a single token, or restores the artist
+        /// default when `royalty` is `None`.
+        #[ink(message)]
+        pub fn set_token_royalty(
+            &mut self,
+            id: TokenId,
+            royalty: Option<(u16, Vec<(AccountId, u16)>)>,
+        ) -> Result<(), Error> {
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            if self.artist_account(token.artist)? != self.env().caller() {
+                return Err(Error::NotArtist)
+            }
+
+            let royalty = match royalty {
+                Some((bps, recipients)) => {
+                    let royalty = self.royalty(bps, recipients)?;
+                    self.token_royalties.insert(id, &royalty);
+                    Some(royalty)
+                }
+                None => {
+                    self.token_royalties.remove(id);
+                    None
+                }
+            };
+            self.env().emit_event(RoyaltySet {
+                artist_id: token.artist,
+                id: Some(id),
+                royalty,
+            });
+            Ok(())
+        }
+
+        /// Computes how a sale of `id` at `sale_price` is split between the royalty
+        /// recipients and the seller.
+        #[ink(message)]
+        pub fn sale_payout(&self, id: TokenId, sale_price: Balance) -> Result<Payout, Error> {
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            let artist_account = self.artist_account(token.artist)?;
+            let royalty = self
+                .token_royalties
+                .get(id)
+                .or_else(|| self.artist_royalties.get(token.artist))
+                .unwrap_or(Royalty {
+                    bps: DEFAULT_ROYALTY_BPS.min(self.max_royalty_bps),
+                    recipients: Vec::new(),
+                });
+
+            let total = bps_of(sale_price, royalty.bps);
+            let mut royalties = Vec::new();
+            if royalty.recipients.is_empty() {
+                royalties.push((artist_account, total));
+            } else {
+                let mut paid = 0;
+                for (recipient, share) in royalty.recipients {
+                    let amount = bps_of(total, share);
+                    royalties.push((recipient, amount));
+                    paid += amount;
+                }
+                // Rounding dust goes to the first recipient.
+                royalties[0].1 += total - paid;
+            }
+            Ok(Payout {
+                royalties,
+                seller_proceeds: sale_price - total,
+            })
+        }
+
+        /// Royalty query for marketplaces trading the tokens through plain PSP34
+        /// transfers, the equivalent of EIP-2981: returns the artist account and the
+        /// total royalty owed for a sale of `id` at `sale_price`. The artist is
+        /// responsible for any split among collaborators.
+        #[ink(message)]
+        pub fn royalty_info(&self, id: TokenId, sale_price: Balance) -> Result<(AccountId, Balance), Error> {
+            let token = self.tokens.get(id).ok_or(Error::TokenNotFound)?;
+            let receiver = self.artist_account(token.artist)?;
+            let payout = self.sale_payout(id, sale_price)?;
+            Ok((receiver, sale_price - payout.seller_proceeds))
+        }
+
+        /// Validates a royalty configuration against the cap and the recipient split.
+        fn royalty(&self, bps: u16, recipients: Vec<(AccountId, u16)>) -> Result<Royalty, Error> {
+            if bps > self.max_royalty_bps {
+                return Err(Error::RoyaltyTooHigh)
+            }
+            if recipients.len() > MAX_ROYALTY_RECIPIENTS as usize {
+                return Err(Error::TooManyRoyaltyRecipients)
+            }
+            let shares: u32 = recipients.iter().map(|(_, share)| u32::from(*share)).sum();
+            if !recipients.is_empty() && shares != u32::from(BASIS_POINTS) {
+                return Err(Error::InvalidRoyaltySplit)
+            }
+            Ok(Royalty { bps, recipients })
+        }
+
+        /// Asks `to` to acknowledge the receipt of `id` if it is a contract.
+        fn check_receiver(&mut self, from: AccountId, to: AccountId, id: Id, data: Vec<u8>) -> Result<(), PSP34Error> {
+            let operator = self.env().caller();
+            match self.before_received(operator, from, to, id, data) {
+                None | Some(Ok(())) => Ok(()),
+                Some(Err(PSP34ReceiverError::TransferRejected(reason))) => {
+                    Err(PSP34Error::SafeTransferCheckFailed(reason))
+                }
+            }
+        }
+
+        /// Calls the `before_received` hook of `to`, or returns `None` when `to` is not a
+        /// contract. The receiver cannot call back into this contract: state kept in
+        /// the root storage struct would be overwritten when the outer call returns.
+        #[cfg(not(test))]
+        fn before_received(
+            &mut self,
+            operator: AccountId,
+            from: AccountId,
+            to: AccountId,
+            id: Id,
+            data: Vec<u8>,
+        ) -> Option<Result<(), PSP34ReceiverError>> {
+            if !self.env().is_contract(&to) {
+                return None
+            }
+
+            let result = PSP34ReceiverRef::before_received_builder(&to, operator, from, id, data).try_invoke();
+            match result {
+                Ok(Ok(response)) => Some(response),
+                _ => {
+                    Some(Err(PSP34ReceiverError::TransferRejected(
+                        "Receiver does not accept PSP34 tokens".into(),
+                    )))
+                }
+            }
+        }
+
+        /// The off-chain environment of ink 4.0 can neither tell contracts apart nor
+        /// call them, so tests declare receiver contracts with `tests::set_receiver`.
+        #[cfg(test)]
+        fn before_received(
+            &mut self,
+            _operator: AccountId,
+            _from: AccountId,
+            to: AccountId,
+            _id: Id,
+            _data: Vec<u8>,
+        ) -> Option<Result<(), PSP34ReceiverError>> {
+            tests::receiver_response(to)
+        }
+
+        /// Revokes every operator `owner` approved for `id` alone.
+        fn revoke_token_operators(&mut self, owner: &AccountId, id: &Id) -> Result<(), PSP34Error> {
+            let token_id = token_id(id)?;
+            for operator in self.token_operators.get(token_id).unwrap_or_default() {
+                self.psp34.operator_approvals.remove(&(owner, &operator, &Some(id)));
+                self._emit_approval_event(*owner, operator, Some(id.clone()), false);
+            }
+            self.token_operators.remove(token_id);
+            Ok(())
+        }
+
+        /// Returns the price `listing` can be bought at right now.
+        fn listing_price(&self, listing: &Listing) -> Balance {
+            let decay = match &listing.decay {
+                Some(decay) => decay,
+                None => return listing.price,
+            };
+            let now = self.env().block_timestamp();
+            if now >= decay.end {
+                return decay.floor
+            }
+
+            let mut elapsed = now.saturating_sub(decay.start);
+            if decay.step > 0 {
+                elapsed -= elapsed % decay.step;
+            }
+            listing.price - fraction_of(listing.price - decay.floor, elapsed, decay.end - decay.start)
+        }
+
+        /// Returns the listing of `token` unless it is stale or expired.
+        fn active_listing(&self, token: &Token) -> Option<Listing> {
+            self.checked_listing(token).ok()
+        }
+
+        /// Returns the listing of `token`, or why it cannot be bought from.
+        fn checked_listing(&self, token: &Token) -> Result<Listing, Error> {
+            let listing = token.listing.clone().ok_or(Error::NotForSale)?;
+            if listing.seller != token.owner {
+                return Err(Error::NotForSale)
+            }
+            if listing.expiry.map_or(false, |expiry| self.env().block_timestamp() >= expiry) {
+                return Err(Error::ListingExpired)
+            }
+            Ok(listing)
+        }
+
+        /// Checks that the caller owns `token` or was approved by its owner.
+        fn ensure_owner_or_approved(&self, token: &Token, id: TokenId) -> Result<(), Error> {
+            self.ensure_acting_for(token.owner, id)
+        }
+
+        /// Checks that the caller is `owner` or one of their operators for `id`.
+        fn ensure_acting_for(&self, owner: AccountId, id: TokenId) -> Result<(), Error> {
+            let caller = self.env().caller();
+            if owner != caller && !self.allowance(owner, caller, Some(Id::U32(id))) {
+                return Err(Error::NotOwner)
+            }
+            Ok(())
+        }
+
+        /// Collects up to `limit` tokens matching `filter`, walking the enumerable
+        /// index from `start` and inspecting at most `MAX_PAGE_SCAN` entries.
+        fn scan_tokens<F>(&self, start: u32, limit: u32, filter: F) -> Page<(TokenId, Token)>
+        where
+            F: Fn(&Token) -> bool,
+        {
+            let total = self.minted_count;
+            let end = start.saturating_add(MAX_PAGE_SCAN).min(total);
+            let mut items = Vec::new();
+            let mut index = start;
+            while index < end && (items.len() as u32) < limit {
+                let token = self
+                    .minted_tokens
+                    .get(index)
+                    .and_then(|id| self.tokens.get(id).map(|token| (id, token)));
+                if let Some((id, token)) = token {
+                    if filter(&token) {
+                        items.push((id, token));
+                    }
+                }
+                index += 1;
+            }
+            Page {
+                items,
+                next: (index < total).then_some(index),
+            }
+        }
+
+        /// Records a new token owned by `owner` and created by `artist_id`, listing it
+        /// when `price` is not zero. Events other than the PSP34 transfer are left to
+        /// the caller.
+        fn mint_token(
+            &mut self,
+            owner: AccountId,
+            id: TokenId,
+            price: Balance,
+            artist_id: ArtistId,
+            metadata: TokenMetadata,
+        ) -> Result<(), Error> {
+            if self.tokens.contains(id) {
+                return Err(PSP34Error::TokenExists.into())
+            }
+            self.register_content_hash(id, None, metadata.content_hash)?;
+            self._mint_to(owner, Id::U32(id))?;
+            self.write_metadata(id, metadata);
+            let listing = (price > 0).then_some(Listing {
+                price,
+                seller: owner,
+                expiry: None,
+                decay: None,
+            });
+            let token = Token {
+                owner,
+                artist: artist_id,
+                listing,
+            };
+            self.tokens.insert(id, &token);
+            self.add_artist_token(artist_id, id);
+            self.minted_tokens.insert(self.minted_count, &id);
+            self.mint_positions.insert(id, &self.minted_count);
+            self.minted_count += 1;
+            Ok(())
+        }
+
+        /// Appends `id` to the tokens created by `artist_id`.
+        fn add_artist_token(&mut self, artist_id: ArtistId, id: TokenId) {
+            let count = self.artist_token_count(artist_id);
+            self.artist_tokens.insert((artist_id, count), &id);
+            self.artist_token_indices.insert(id, &count);
+            self.artist_token_counts.insert(artist_id, &(count + 1));
+        }
+
+        /// Removes `id` from the tokens created by `artist_id`, moving the last token
+        /// of the artist into its slot.
+        fn remove_artist_token(&mut self, artist_id: ArtistId, id: TokenId) {
+            let Some(index) = self.artist_token_indices.get(id) else {
+                return
+            };
+            let last = self.artist_token_count(artist_id) - 1;
+            if index != last {
+                if let Some(last_id) = self.artist_tokens.get((artist_id, last)) {
+                    self.artist_tokens.insert((artist_id, index), &last_id);
+                    self.artist_token_indices.insert(last_id, &index);
+                }
+            }
+            self.artist_tokens.remove((artist_id, last));
+            self.artist_token_indices.remove(id);
+            self.artist_token_counts.insert(artist_id, &last);
+        }
+
+        /// Moves the provenance record of `id` from `old` to `new`, rejecting content
+        /// already registered by another token.
+        fn register_content_hash(&mut self, id: TokenId, old: Option<Hash>, new: Option<Hash>) -> Result<(), Error> {
+            if old == new {
+                return Ok(())
+            }
+            if let Some(new) = new {
+                if self.content_hashes.contains(new) {
+                    return Err(Error::DuplicateContent)
+                }
+                self.content_hashes.insert(new, &id);
+            }
+            if let Some(old) = old {
+                self.content_hashes.remove(old);
+            }
+            Ok(())
+        }
+
+        /// Stores `metadata` as `PSP34Metadata` attributes of `id`.
+        fn write_metadata(&mut self, id: TokenId, metadata: TokenMetadata) {
+            let content_hash = metadata
+                .content_hash
+                .map(|hash| hash.as_ref().to_vec())
+                .unwrap_or_default();
+            for (key, value) in [
+                (NAME_KEY, metadata.name),
+                (DESCRIPTION_KEY, metadata.description),
+                (URI_KEY, metadata.uri),
+                (CONTENT_HASH_KEY, content_hash),
+            ] {
+                // Skip empty fields unless they clear a previous value.
+                if !value.is_empty() || self.get_attribute(Id::U32(id), key.to_vec()).is_some() {
+                    self._set_attribute(Id::U32(id), key.to_vec(), value);
+                }
+            }
+            if metadata.mutable {
+                self.mutable_metadata.insert(id, &());
+            } else {
+                self.mutable_metadata.remove(id);
+            }
+        }
+
+        /// Hands `id` from `holder` over to `buyer` and pays `price`, already held by
+        /// the contract, out to the royalty recipients and `seller`. A failed payment
+        /// fails the sale, unless `defer_failures` is set: auction settlements leave it
+        /// for `withdraw` so that a single recipient cannot lock the auction.
+        fn complete_sale(
+            &mut self,
+            id: TokenId,
+            holder: AccountId,
+            seller: AccountId,
+            buyer: AccountId,
+            price: Balance,
+            defer_failures: bool,
+        ) -> Result<(), Error> {
+            let payout = self.sale_payout(id, price)?;
+            self.move_token(holder, buyer, id)?;
+
+            let payments = payout
+                .royalties
+                .iter()
+                .copied()
+                .chain(core::iter::once((seller, payout.seller_proceeds)));
+            for (recipient, amount) in payments {
+                if defer_failures {
+                    self.pay_or_defer(recipient, amount);
+                } else {
+                    self.pay(recipient, amount)?;
+                }
+            }
+
+            self.env().emit_event(Sold {
+                id,
+                seller,
+                buyer,
+                price,
+                artist_share: price - payout.seller_proceeds,
+                seller_proceeds: payout.seller_proceeds,
+            });
+            Ok(())
+        }
+
+        /// Sends `amount` from the contract balance to `to`.
+        fn pay(&self, to: AccountId, amount: Balance) -> Result<(), Error> {
+            if amount == 0 {
+                return Ok(())
+            }
+            self.env().transfer(to, amount).map_err(|_| Error::TransferFailed)
+        }
+
+        /// Sends `amount` to `to`, or records it for `withdraw` when the transfer fails,
+        /// e.g. because it would leave `to` below the existential deposit.
+        fn pay_or_defer(&mut self, to: AccountId, amount: Balance) {
+            if self.pay(to, amount).is_ok() {
+                return
+            }
+            let pending = self.pending_payments.get(to).unwrap_or_default();
+            self.pending_payments.insert(to, &(pending + amount));
+            self.env().emit_event(PaymentDeferred { account: to, amount });
+        }
+
+        /// Moves `id` between accounts on behalf of the contract itself,
+        /// bypassing the caller approval check of `PSP34::transfer`.
+        fn move_token(&mut self, from: AccountId, to: AccountId, id: TokenId) -> Result<(), PSP34Error> {
+            let id = Id::U32(id);
+            self._before_token_transfer(Some(&from), Some(&to), &id)?;
+            self.psp34.balances.decrease_balance(&from, &id, false);
+            self.psp34.balances.increase_balance(&to, &id, false);
+            self.psp34.token_owner.insert(&id, &to);
+            self._after_token_transfer(Some(&from), Some(&to), &id)?;
+            self._emit_transfer_event(Some(from), Some(to), id);
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use ink::env::{
+            test,
+            DefaultEnvironment,
+        };
+        use std::cell::RefCell;
+
+        thread_local! {
+            /// Receiver contracts of the running test, with the reason they reject
+            /// tokens for, if any.
+            static RECEIVERS: RefCell<Vec<(AccountId, Option<String>)>> = RefCell::new(Vec::new());
+        }
+
+        /// Declares `account` a contract that accepts tokens, or rejects them for
+        /// `rejection`.
+        fn set_receiver(account: AccountId, rejection: Option<&str>) {
+            RECEIVERS.with(|receivers| {
+                receivers
+                    .borrow_mut()
+                    .push((account, rejection.map(String::from)))
+            });
+        }
+
+        pub(super) fn receiver_response(account: AccountId) -> Option<Result<(), PSP34ReceiverError>> {
+            RECEIVERS.with(|receivers| {
+                let receivers = receivers.borrow();
+                let (_, rejection) = receivers.iter().find(|(receiver, _)| *receiver == account)?;
+                Some(match rejection {
+                    Some(reason) => Err(PSP34ReceiverError::TransferRejected(reason.clone())),
+                    None => Ok(()),
+                })
+            })
+        }
+
+        fn accounts() -> test::DefaultAccounts<DefaultEnvironment> {
+            test::default_accounts::<DefaultEnvironment>()
+        }
+
+        fn set_caller(caller: AccountId) {
+            test::set_caller::<DefaultEnvironment>(caller);
+        }
+
+        type Event = <Contract as ink::reflect::ContractEventBase>::Type;
+
+        /// Decodes the recorded events, each with its number of topics.
+        fn recorded_events() -> Vec<(Event, usize)> {
+            test::recorded_events()
+                .map(|event| {
+                    let decoded = <Event as scale::Decode>::decode(&mut &event.data[..]).expect("invalid event data");
+                    (decoded, event.topics.len())
+                })
+                .collect()
+        }
+
+        fn with_artist() -> Contract {
+            let mut psp34 = Contract::new(2_500);
+            psp34.register_artist(b"Artist 1".to_vec()).unwrap();
+            psp34
+        }
+
+        #[ink::test]
+        fn test_mint() {
+            let mut psp34 = with_artist();
+            let caller = accounts().alice;
+
+            assert_eq!(psp34.mint(0, 100, 0, TokenMetadata::default()), Ok(()));
+            let token = psp34.get_token(0).unwrap();
+            assert_eq!(token, Token {
+                owner: caller,
+                artist: 0,
+                listing: Some(Listing {
+                    price: 100,
+                    seller: caller,
+                    expiry: None,
+                    decay: None,
+                }),
+            });
+
+            // Minting the same id twice or for an unknown artist fails
+            assert_eq!(
+                psp34.mint(0, 100, 0, TokenMetadata::default()),
+                Err(Error::PSP34Error(PSP34Error::TokenExists))
+            );
+            assert_eq!(psp34.mint(1, 100, 1, TokenMetadata::default()), Err(Error::ArtistNotFound));
+        }
+
+        #[ink::test]
+        fn test_batch_mint() {
+            let mut psp34 = with_artist();
+            let artist = accounts().alice;
+            let items = (0..3)
+                .map(|id| (id, TokenMetadata::default(), u128::from(id) * 100))
+                .collect::<Vec<_>>();
+
+            assert_eq!(psp34.mint_batch(items), Ok(()));
+            assert_eq!(psp34.balance_of(artist), 3);
+            assert!(psp34.get_token(0).unwrap().listing.is_none());
+            assert_eq!(psp34.get_token(2).unwrap().listing.unwrap().price, 200);
+
+            assert_eq!(psp34.mint_range(100, 50, b"ipfs://series/".to_vec()), Ok(()));
+            assert_eq!(psp34.total_supply(), 53);
+            assert_eq!(psp34.artist_token_count(0), 53);
+            psp34.set_base_uri(b"ipfs://drop/".to_vec(), b".json".to_vec()).unwrap();
+            assert_eq!(psp34.token_uri(149), Some(b"ipfs://series/149.json".to_vec()));
+            assert_eq!(psp34.token_uri(1), Some(b"ipfs://drop/1.json".to_vec()));
+
+            // Reminting the burned first token of a range leaves the rest of the range alone
+            assert_eq!(psp34.burn(100), Ok(()));
+            assert_eq!(psp34.mint_range(100, 1, b"ipfs://other/".to_vec()), Ok(()));
+            assert_eq!(psp34.token_uri(100), Some(b"ipfs://other/100.json".to_vec()));
+            assert_eq!(psp34.token_uri(101), Some(b"ipfs://series/101.json".to_vec()));
+
+            // Batches are bounded and reserved to registered artists
+            assert_eq!(psp34.mint_range(1_000, 0, Vec::new()), Err(Error::InvalidBatchSize));
+            assert_eq!(
+                psp34.mint_range(1_000, MAX_BATCH_SIZE + 1, Vec::new()),
+                Err(Error::InvalidBatchSize)
+            );
+            assert_eq!(psp34.mint_batch(Vec::new()), Err(Error::InvalidBatchSize));
+            set_caller(accounts().bob);
+            assert_eq!(psp34.mint_range(1_000, 1, Vec::new()), Err(Error::NotArtist));
+        }
+
+        #[ink::test]
+        fn test_mint_restricted_to_artist() {
+            let mut psp34 = with_artist();
+            let gallery = accounts().bob;
+
+            // Other accounts cannot mint in the name of the artist
+            set_caller(gallery);
+            assert_eq!(psp34.mint(0, 0, 0, TokenMetadata::default()), Err(Error::NotArtist));
+            assert_eq!(psp34.set_minter(0, gallery, true), Err(Error::NotArtist));
+
+            // Unless the artist authorizes them
+            set_caller(accounts().alice);
+            assert_eq!(psp34.set_minter(0, gallery, true), Ok(()));
+            set_caller(gallery);
+            assert_eq!(psp34.mint(0, 0, 0, TokenMetadata::default()), Ok(()));
+            assert_eq!(psp34.get_token(0).unwrap().artist, 0);
+
+            set_caller(accounts().alice);
+            assert_eq!(psp34.set_minter(0, gallery, false), Ok(()));
+            set_caller(gallery);
+            assert_eq!(psp34.mint(1, 0, 0, TokenMetadata::default()), Err(Error::NotArtist));
+        }
+
+        #[ink::test]
+        fn test_transfer() {
+            let mut psp34 = with_artist();
+            let caller1 = accounts().alice;
+            let caller2 = accounts().bob;
+
+            psp34.mint(0, 100, 0, TokenMetadata::default()).unwrap();
+
+            // Try to transfer token to another account
+            assert!(psp34.transfer(caller2, Id::U32(0), Vec::new()).is_ok());
+            let token = psp34.get_token(0).unwrap();
+            assert_eq!(token, Token {
+                owner: caller2,
+                artist: 0,
+                listing: None,
+            });
+
+            // Try to transfer token from the previous owner (should fail)
+            assert!(psp34.transfer(caller1, Id::U32(0), Vec::new()).is_err());
+            let token = psp34.get_token(0).unwrap();
+            assert_eq!(token.owner, caller2);
+        }
+
+        #[ink::test]
+        fn test_safe_transfer() {
+            let mut psp34 = with_artist();
+            let (bob, charlie, eve) = (accounts().bob, accounts().charlie, accounts().eve);
+            set_receiver(charlie, None);
+            set_receiver(eve, Some("No NFTs"));
+
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            psp34.mint(1, 0, 0, TokenMetadata::default()).unwrap();
+
+            // Plain accounts are not asked to acknowledge the token
+            assert_eq!(psp34.transfer(bob, Id::U32(0), Vec::new()), Ok(()));
+
+            // A receiver contract that acknowledges the token gets it
+            assert_eq!(psp34.transfer(charlie, Id::U32(1), Vec::new()), Ok(()));
+            assert_eq!(psp34.owner_of(Id::U32(1)), Some(charlie));
+
+            // A receiver contract that rejects the token fails the transfer
+            set_caller(bob);
+            assert_eq!(
+                psp34.transfer(eve, Id::U32(0), Vec::new()),
+                Err(PSP34Error::SafeTransferCheckFailed(String::from("No NFTs")))
+            );
+            set_caller(charlie);
+            assert_eq!(
+                psp34.transfer_batch(vec![(1, eve)]),
+                Err((Some(0), Error::PSP34Error(PSP34Error::SafeTransferCheckFailed(String::from("No NFTs")))))
+            );
+        }
+
+        #[ink::test]
+        fn test_approvals() {
+            let mut psp34 = with_artist();
+            let owner = accounts().alice;
+            let gallery = accounts().bob;
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            psp34.mint(1, 0, 0, TokenMetadata::default()).unwrap();
+
+            // Unapproved accounts can neither list nor move the token
+            set_caller(gallery);
+            assert_eq!(psp34.set_price(0, 100), Err(Error::NotOwner));
+            assert!(psp34.transfer(gallery, Id::U32(0), Vec::new()).is_err());
+
+            // A per-token approval lets the operator list the token for the owner
+            set_caller(owner);
+            assert_eq!(psp34.approve(gallery, Some(Id::U32(0)), true), Ok(()));
+            assert!(psp34.allowance(owner, gallery, Some(Id::U32(0))));
+            set_caller(gallery);
+            assert_eq!(psp34.set_price(0, 100), Ok(()));
+            assert_eq!(psp34.get_token(0).unwrap().listing.unwrap().seller, owner);
+            assert_eq!(psp34.set_price(1, 100), Err(Error::NotOwner));
+
+            // Transferring the token revokes the approval
+            set_caller(owner);
+            psp34.transfer(accounts().charlie, Id::U32(0), Vec::new()).unwrap();
+            assert!(!psp34.allowance(owner, gallery, Some(Id::U32(0))));
+
+            // An operator approved for all tokens can move any of them
+            assert_eq!(psp34.approve(gallery, None, true), Ok(()));
+            set_caller(gallery);
+            assert_eq!(psp34.transfer(accounts().django, Id::U32(1), Vec::new()), Ok(()));
+            assert_eq!(psp34.owner_of(Id::U32(1)), Some(accounts().django));
+        }
+
+        #[ink::test]
+        fn test_burn() {
+            let mut psp34 = with_artist();
+            let artist = accounts().alice;
+            let gallery = accounts().bob;
+            let collector = accounts().charlie;
+            let artwork = TokenMetadata {
+                content_hash: Some(Hash::from([0x1; 32])),
+                ..Default::default()
+            };
+            psp34.mint(0, 100, 0, artwork).unwrap();
+            psp34.mint(1, 0, 0, TokenMetadata::default()).unwrap();
+            psp34.mint(2, 0, 0, TokenMetadata::default()).unwrap();
+
+            // Only the owner or an approved operator can burn
+            set_caller(gallery);
+            assert_eq!(psp34.burn(0), Err(Error::NotOwner));
+            set_caller(artist);
+            assert_eq!(psp34.burn(0), Ok(()));
+            assert_eq!(psp34.get_token(0), None);
+            assert_eq!(psp34.owner_of(Id::U32(0)), None);
+            assert_eq!(psp34.total_supply(), 2);
+            assert_eq!(psp34.token_by_content_hash(Hash::from([0x1; 32])), None);
+            assert_eq!(psp34.artist_token_count(0), 2);
+            assert_eq!(psp34.artist_token_by_index(0, 0), Ok(2));
+            assert_eq!(psp34.burn(0), Err(Error::TokenNotFound));
+
+            // The artist can burn unsold tokens held by their minters, but not sold ones
+            psp34.set_minter(0, gallery, true).unwrap();
+            psp34.transfer(gallery, Id::U32(1), Vec::new()).unwrap();
+            psp34.transfer(collector, Id::U32(2), Vec::new()).unwrap();
+            assert_eq!(psp34.burn_unsold(1), Ok(()));
+            assert_eq!(psp34.burn_unsold(2), Err(Error::TokenSold));
+            set_caller(gallery);
+            assert_eq!(psp34.burn_unsold(2), Err(Error::NotArtist));
+        }
+
+        #[ink::test]
+        fn test_batch_transfer_and_listing() {
+            let mut psp34 = with_artist();
+            let collector = accounts().bob;
+            psp34.mint_range(0, 4, Vec::new()).unwrap();
+
+            assert_eq!(psp34.list_batch(vec![(0, 100), (1, 200)]), Ok(()));
+            assert_eq!(psp34.get_token(1).unwrap().listing.unwrap().price, 200);
+            assert_eq!(psp34.list_batch(vec![(2, 100), (9, 100)]), Err((Some(1), Error::TokenNotFound)));
+
+            assert_eq!(psp34.transfer_batch(vec![(0, collector), (3, collector)]), Ok(()));
+            assert_eq!(psp34.balance_of(collector), 2);
+            assert!(psp34.get_token(0).unwrap().listing.is_none());
+            assert_eq!(
+                psp34.transfer_batch(vec![(1, collector), (0, collector)]),
+                Err((Some(1), Error::PSP34Error(PSP34Error::NotApproved)))
+            );
+
+            // Batches are bounded the same way as minting batches
+            let oversized = vec![(1, collector); MAX_BATCH_SIZE as usize + 1];
+            assert_eq!(psp34.transfer_batch(oversized), Err((None, Error::InvalidBatchSize)));
+            assert_eq!(psp34.transfer_batch(Vec::new()), Err((None, Error::InvalidBatchSize)));
+            assert_eq!(psp34.list_batch(Vec::new()), Err((None, Error::InvalidBatchSize)));
+        }
+
+        #[ink::test]
+        fn test_psp34_queries() {
+            let mut psp34 = with_artist();
+            let caller = accounts().alice;
+
+            psp34.mint(7, 100, 0, TokenMetadata::default()).unwrap();
+            assert_eq!(psp34.owner_of(Id::U32(7)), Some(caller));
+            assert_eq!(psp34.balance_of(caller), 1);
+            assert_eq!(psp34.total_supply(), 1);
+        }
+
+        #[ink::test]
+        fn test_enumerable() {
+            let mut psp34 = with_artist();
+            let artist = accounts().alice;
+            let collector = accounts().bob;
+            for id in [3, 5, 8] {
+                psp34.mint(id, 0, 0, TokenMetadata::default()).unwrap();
+            }
+            psp34.transfer(collector, Id::U32(5), Vec::new()).unwrap();
+
+            assert_eq!(psp34.token_by_index(2), Ok(Id::U32(8)));
+            assert_eq!(psp34.owners_token_by_index(collector, 0), Ok(Id::U32(5)));
+            assert_eq!(psp34.owners_token_by_index(artist, 1), Ok(Id::U32(8)));
+            assert_eq!(
+                psp34.owners_token_by_index(artist, 2),
+                Err(PSP34Error::TokenNotExists)
+            );
+
+            // Transfers do not affect the works of the artist
+            assert_eq!(psp34.artist_token_count(0), 3);
+            assert_eq!(psp34.artist_token_by_index(0, 1), Ok(5));
+            assert_eq!(psp34.artist_token_by_index(0, 3), Err(Error::TokenNotFound));
+        }
+
+        #[ink::test]
+        fn test_pagination() {
+            let mut psp34 = with_artist();
+            for id in 0..5 {
+                psp34.mint(id, u128::from(id % 2) * 100, 0, TokenMetadata::default()).unwrap();
+            }
+            set_caller(accounts().bob);
+            psp34.register_artist(b"Artist 2".to_vec()).unwrap();
+
+            let page = psp34.list_tokens(0, 3);
+            assert_eq!(page.items.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![0, 1, 2]);
+            assert_eq!(page.next, Some(3));
+            let page = psp34.list_tokens(3, 3);
+            assert_eq!(page.items.len(), 2);
+            assert_eq!(page.next, None);
+
+            // Burning and minting between pages neither skips nor repeats tokens
+            set_caller(accounts().alice);
+            let page = psp34.list_tokens(0, 2);
+            psp34.burn(0).unwrap();
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            let page = psp34.list_tokens(page.next.unwrap(), 10);
+            assert_eq!(page.items.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![2, 3, 4, 0]);
+            set_caller(accounts().bob);
+
+            let page = psp34.list_for_sale(0, 10);
+            assert_eq!(page.items.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![1, 3]);
+            assert_eq!(page.next, None);
+
+            let page = psp34.list_artists(0, 1);
+            assert_eq!(page.items.len(), 1);
+            assert_eq!(page.next, Some(1));
+            let page = psp34.list_artists(1, 1);
+            assert_eq!(page.items[0].1.account_id, accounts().bob);
+            assert_eq!(page.next, None);
+        }
+
+        #[ink::test]
+        fn test_register_artist() {
+            let mut psp34 = Contract::new(2_500);
+            let caller = accounts().alice;
+
+            // Artist ids are allocated in order
+            assert_eq!(psp34.register_artist(b"Artist 1".to_vec()), Ok(0));
+            set_caller(accounts().bob);
+            assert_eq!(psp34.register_artist(b"Artist 2".to_vec()), Ok(1));
+            assert_eq!(psp34.next_artist_id(), 2);
+
+            // Check artist details
+            assert_eq!(psp34.artist_account(0), Ok(caller));
+            assert_eq!(psp34.artist_id_of(caller), Some(0));
+            assert_eq!(psp34.artist_account(2), Err(Error::ArtistNotFound));
+
+            // An account can only register once
+            assert_eq!(
+                psp34.register_artist(b"Artist 2 again".to_vec()),
+                Err(Error::AlreadyRegistered)
+            );
+
+            // Only the artist can update their profile
+            assert_eq!(psp34.update_artist(0, b"Squatter".to_vec()), Err(Error::NotArtist));
+            set_caller(caller);
+            assert_eq!(psp34.update_artist(0, b"Artist 1 (renamed)".to_vec()), Ok(()));
+            assert_eq!(psp34.get_artist(0), Some(Artist {
+                name: b"Artist 1 (renamed)".to_vec(),
+                account_id: caller,
+            }));
+        }
+
+        #[ink::test]
+        fn test_set_price() {
+            let mut psp34 = with_artist();
+            psp34.mint(0, 100, 0, TokenMetadata::default()).unwrap();
+
+            assert_eq!(psp34.set_price(0, 150), Ok(()));
+            assert_eq!(psp34.set_price(1, 150), Err(Error::TokenNotFound));
+
+            set_caller(accounts().bob);
+            assert_eq!(psp34.set_price(0, 10), Err(Error::NotOwner));
+        }
+
+        #[ink::test]
+        fn test_metadata() {
+            let mut psp34 = with_artist();
+            let metadata = TokenMetadata {
+                name: b"Sunflowers".to_vec(),
+                description: b"Oil on canvas".to_vec(),
+                uri: b"ipfs://sunflowers".to_vec(),
+                content_hash: Some(Hash::from([0x1; 32])),
+                mutable: true,
+            };
+            psp34.mint(0, 0, 0, metadata.clone()).unwrap();
+            assert_eq!(psp34.token_metadata(0), Some(metadata.clone()));
+            assert_eq!(
+                psp34.get_attribute(Id::U32(0), NAME_KEY.to_vec()),
+                Some(b"Sunflowers".to_vec())
+            );
+
+            // Only the artist may update mutable metadata
+            set_caller(accounts().bob);
+            assert_eq!(psp34.update_metadata(0, metadata.clone()), Err(Error::NotArtist));
+
+            // Updating with immutable metadata freezes it
+            set_caller(accounts().alice);
+            let frozen = TokenMetadata {
+                mutable: false,
+                ..metadata
+            };
+            assert_eq!(psp34.update_metadata(0, frozen.clone()), Ok(()));
+            assert_eq!(psp34.update_metadata(0, frozen), Err(Error::MetadataFrozen));
+
+            // Collection attributes are reserved to the contract owner
+            assert_eq!(psp34.set_collection_attribute(b"name".to_vec(), b"Gallery".to_vec()), Ok(()));
+            assert_eq!(
+                psp34.get_attribute(psp34.collection_id(), b"name".to_vec()),
+                Some(b"Gallery".to_vec())
+            );
+            set_caller(accounts().bob);
+            assert_eq!(
+                psp34.set_collection_attribute(b"name".to_vec(), b"Mine".to_vec()),
+                Err(Error::OwnableError(OwnableError::CallerIsNotOwner))
+            );
+        }
+
+        #[ink::test]
+        fn test_content_hash_provenance() {
+            let mut psp34 = with_artist();
+            let artwork = TokenMetadata {
+                content_hash: Some(Hash::from([0x1; 32])),
+                mutable: true,
+                ..Default::default()
+            };
+            psp34.mint(0, 0, 0, artwork.clone()).unwrap();
+            assert_eq!(psp34.token_by_content_hash(Hash::from([0x1; 32])), Some(0));
+
+            // The same artwork cannot be minted twice
+            assert_eq!(psp34.mint(1, 0, 0, artwork.clone()), Err(Error::DuplicateContent));
+
+            // Changing the hash of mutable metadata releases the old one
+            let revised = TokenMetadata {
+                content_hash: Some(Hash::from([0x2; 32])),
+                ..artwork.clone()
+            };
+            assert_eq!(psp34.update_metadata(0, revised), Ok(()));
+            assert_eq!(psp34.token_by_content_hash(Hash::from([0x1; 32])), None);
+            assert_eq!(psp34.token_by_content_hash(Hash::from([0x2; 32])), Some(0));
+            assert_eq!(psp34.mint(1, 0, 0, artwork), Ok(()));
+        }
+
+        #[ink::test]
+        fn test_token_uri() {
+            let mut psp34 = with_artist();
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            let own_uri = TokenMetadata {
+                uri: b"ipfs://unique".to_vec(),
+                ..Default::default()
+            };
+            psp34.mint(1, 0, 0, own_uri).unwrap();
+            assert_eq!(psp34.token_uri(0), None);
+
+            assert_eq!(psp34.set_base_uri(b"ipfs://drop/".to_vec(), b".json".to_vec()), Ok(()));
+            assert_eq!(psp34.token_uri(0), Some(b"ipfs://drop/0.json".to_vec()));
+            assert_eq!(psp34.token_uri(1), Some(b"ipfs://unique".to_vec()));
+            assert_eq!(psp34.token_uri(2), None);
+
+            // Only the owner can change the base URI, and not after freezing
+            set_caller(accounts().bob);
+            assert_eq!(
+                psp34.set_base_uri(Vec::new(), Vec::new()),
+                Err(Error::OwnableError(OwnableError::CallerIsNotOwner))
+            );
+            set_caller(accounts().alice);
+            assert_eq!(psp34.freeze_metadata(), Ok(()));
+            assert!(psp34.metadata_frozen());
+            assert_eq!(psp34.set_base_uri(Vec::new(), Vec::new()), Err(Error::MetadataFrozen));
+            assert_eq!(psp34.freeze_metadata(), Err(Error::MetadataFrozen));
+        }
+
+        #[ink::test]
+        fn test_royalties() {
+            let mut psp34 = with_artist();
+            let artist = accounts().alice;
+            let collaborator = accounts().charlie;
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+
+            // Without configuration the artist receives the default royalty
+            assert_eq!(psp34.sale_payout(0, 1_000), Ok(Payout {
+                royalties: vec![(artist, 100)],
+                seller_proceeds: 900,
+            }));
+
+            // Artist defaults can be split among collaborators
+            assert_eq!(
+                psp34.set_artist_royalty(0, 500, vec![(artist, 5_000), (collaborator, 5_000)]),
+                Ok(())
+            );
+            assert_eq!(psp34.sale_payout(0, 1_000), Ok(Payout {
+                royalties: vec![(artist, 25), (collaborator, 25)],
+                seller_proceeds: 950,
+            }));
+            assert_eq!(psp34.royalty_info(0, 1_000), Ok((artist, 50)));
+            assert_eq!(psp34.royalty_info(1, 1_000), Err(Error::TokenNotFound));
+
+            // Tokens can override the artist default, within the cap
+            assert_eq!(
+                psp34.set_token_royalty(0, Some((3_000, Vec::new()))),
+                Err(Error::RoyaltyTooHigh)
+            );
+            assert_eq!(
+                psp34.set_token_royalty(0, Some((2_000, vec![(collaborator, 9_000)]))),
+                Err(Error::InvalidRoyaltySplit)
+            );
+            assert_eq!(
+                psp34.set_token_royalty(0, Some((2_000, vec![(collaborator, 1_000); 10]))),
+                Ok(())
+            );
+            assert_eq!(
+                psp34.set_token_royalty(0, Some((2_000, vec![(collaborator, 1_000); 11]))),
+                Err(Error::TooManyRoyaltyRecipients)
+            );
+            assert_eq!(psp34.set_token_royalty(0, Some((2_000, Vec::new()))), Ok(()));
+            assert_eq!(psp34.sale_payout(0, 1_000), Ok(Payout {
+                royalties: vec![(artist, 200)],
+                seller_proceeds: 800,
+            }));
+
+            // Only the artist can configure royalties
+            set_caller(accounts().bob);
+            assert_eq!(psp34.set_token_royalty(0, None), Err(Error::NotArtist));
+            assert_eq!(psp34.set_artist_royalty(0, 0, Vec::new()), Err(Error::NotArtist));
+        }
+
+        #[ink::test]
+        fn test_listing() {
+            let mut psp34 = with_artist();
+            let owner = accounts().alice;
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+
+            assert_eq!(psp34.list(0, 0, None), Err(Error::InvalidPrice));
+            assert_eq!(psp34.list(0, 100, Some(1_000)), Err(Error::InvalidExpiry));
+            assert_eq!(psp34.list(0, 100, Some(2_000)), Ok(()));
+            assert_eq!(psp34.list(0, 100, None), Err(Error::AlreadyListed));
+            assert_eq!(psp34.update_listing(0, 150), Ok(()));
+            assert_eq!(psp34.get_token(0).unwrap().listing, Some(Listing {
+                price: 150,
+                seller: owner,
+                expiry: Some(2_000),
+                decay: None,
+            }));
+
+            // Expired listings can no longer be bought or updated, only replaced
+            test::set_block_timestamp::<DefaultEnvironment>(2_000);
+            set_caller(accounts().bob);
+            test::set_value_transferred::<DefaultEnvironment>(150);
+            assert_eq!(psp34.buy(0), Err(Error::ListingExpired));
+            assert_eq!(psp34.delist(0), Err(Error::NotOwner));
+            set_caller(owner);
+            assert_eq!(psp34.update_listing(0, 200), Err(Error::ListingExpired));
+            assert_eq!(psp34.list(0, 200, None), Ok(()));
+
+            assert_eq!(psp34.delist(0), Ok(()));
+            assert_eq!(psp34.delist(0), Err(Error::NotForSale));
+            assert_eq!(psp34.update_listing(0, 200), Err(Error::NotForSale));
+
+            // Transferring the token invalidates its listing
+            psp34.list(0, 100, None).unwrap();
+            psp34.transfer(accounts().charlie, Id::U32(0), Vec::new()).unwrap();
+            assert_eq!(psp34.get_token(0).unwrap().listing, None);
+        }
+
+        #[ink::test]
+        fn test_events() {
+            let mut psp34 = with_artist();
+            let (alice, bob) = (accounts().alice, accounts().bob);
+            psp34.update_artist(0, b"Artist 1 (renamed)".to_vec()).unwrap();
+
+            // Mint emits the PSP34 transfer plus the mint and listing events
+            psp34.mint(0, 100, 0, TokenMetadata::default()).unwrap();
+            psp34.set_price(0, 200).unwrap();
+            psp34.delist(0).unwrap();
+            psp34.list(0, 150, Some(5_000)).unwrap();
+
+            // Buying delists the token, moves it and reports the split of the price
+            set_caller(bob);
+            test::set_account_balance::<DefaultEnvironment>(test::callee::<DefaultEnvironment>(), 150);
+            test::set_value_transferred::<DefaultEnvironment>(150);
+            psp34.buy(0).unwrap();
+
+            let events = recorded_events();
+            assert_eq!(events.len(), 11);
+            match &events[..] {
+                [
+                    (Event::ArtistRegistered(registered), 3),
+                    (Event::ArtistUpdated(updated), 3),
+                    (Event::Transfer(minted_transfer), 4),
+                    (Event::Minted(minted), 4),
+                    (Event::Listed(listed), 3),
+                    (Event::PriceChanged(price_changed), 2),
+                    (Event::Delisted(delisted), 2),
+                    (Event::Listed(relisted), 3),
+                    (Event::Delisted(sold_delisted), 2),
+                    (Event::Transfer(sold_transfer), 4),
+                    (Event::Sold(sold), 4),
+                ] => {
+                    assert_eq!((registered.artist_id, registered.account_id), (0, alice));
+                    assert_eq!(updated.name, b"Artist 1 (renamed)".to_vec());
+                    assert_eq!(
+                        (minted_transfer.from, minted_transfer.to, &minted_transfer.id),
+                        (None, Some(alice), &Id::U32(0))
+                    );
+                    assert_eq!((minted.id, minted.artist_id, minted.owner, minted.price), (0, 0, alice, 100));
+                    assert_eq!((listed.id, listed.seller, listed.price, listed.expiry), (0, alice, 100, None));
+                    assert_eq!(
+                        (price_changed.id, price_changed.old_price, price_changed.new_price),
+                        (0, 100, 200)
+                    );
+                    assert_eq!(delisted.id, 0);
+                    assert_eq!((relisted.price, relisted.expiry), (150, Some(5_000)));
+                    assert_eq!(sold_delisted.id, 0);
+                    assert_eq!(
+                        (sold_transfer.from, sold_transfer.to, &sold_transfer.id),
+                        (Some(alice), Some(bob), &Id::U32(0))
+                    );
+                    assert_eq!((sold.id, sold.seller, sold.buyer, sold.price), (0, alice, bob, 150));
+                    assert_eq!((sold.artist_share, sold.seller_proceeds), (15, 135));
+                }
+                _ => panic!("unexpected events: {:?}", events.iter().map(|(_, topics)| topics).collect::<Vec<_>>()),
+            }
+        }
+
+        #[ink::test]
+        fn test_buy() {
+            let mut psp34 = with_artist();
+            let artist = accounts().alice;
+            let seller = accounts().django;
+            let buyer = accounts().bob;
+
+            // The artist mints and hands the token to a collector
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            psp34.transfer(seller, Id::U32(0), Vec::new()).unwrap();
+
+            // Try to buy a token that is not for sale (should fail)
+            set_caller(buyer);
+            assert_eq!(psp34.buy(0), Err(Error::NotForSale));
+
+            set_caller(seller);
+            psp34.set_price(0, 100).unwrap();
+
+            // Try to buy without covering the price (should fail)
+            set_caller(buyer);
+            test::set_value_transferred::<DefaultEnvironment>(50);
+            assert_eq!(psp34.buy(0), Err(Error::WrongPrice));
+
+            let contract = test::callee::<DefaultEnvironment>();
+            let artist_balance = test::get_account_balance::<DefaultEnvironment>(artist).unwrap();
+            let seller_balance = test::get_account_balance::<DefaultEnvironment>(seller).unwrap();
+
+            // Overpaying refunds the difference to the buyer
+            test::set_account_balance::<DefaultEnvironment>(contract, 120);
+            let buyer_balance = test::get_account_balance::<DefaultEnvironment>(buyer).unwrap();
+            test::set_value_transferred::<DefaultEnvironment>(120);
+            assert_eq!(psp34.buy(0), Ok(()));
+            assert_eq!(psp34.get_token(0).unwrap(), Token {
+                owner: buyer,
+                artist: 0,
+                listing: None,
+            });
+            assert_eq!(psp34.owner_of(Id::U32(0)), Some(buyer));
+            assert_eq!(
+                test::get_account_balance::<DefaultEnvironment>(artist),
+                Ok(artist_balance + 10)
+            );
+            assert_eq!(
+                test::get_account_balance::<DefaultEnvironment>(seller),
+                Ok(seller_balance + 90)
+            );
+            assert_eq!(
+                test::get_account_balance::<DefaultEnvironment>(buyer),
+                Ok(buyer_balance + 20)
+            );
+            assert_eq!(test::get_account_balance::<DefaultEnvironment>(contract), Ok(0));
+        }
+
+        #[ink::test]
+        fn test_deferred_payments() {
+            let mut psp34 = with_artist();
+            let artist = accounts().alice;
+            let (bob, charlie) = (accounts().bob, accounts().charlie);
+            let contract = test::callee::<DefaultEnvironment>();
+            let balance_of = |account| test::get_account_balance::<DefaultEnvironment>(account).unwrap();
+
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+            assert_eq!(psp34.create_auction(0, 100, 1_000, 2_000, 10), Ok(()));
+
+            // A refund that cannot be paid does not block the next bid
+            set_caller(bob);
+            test::set_value_transferred::<DefaultEnvironment>(100);
+            assert_eq!(psp34.bid(0), Ok(()));
+            test::set_account_balance::<DefaultEnvironment>(contract, 0);
+            set_caller(charlie);
+            test::set_value_transferred::<DefaultEnvironment>(200);
+            assert_eq!(psp34.bid(0), Ok(()));
+            assert_eq!(psp34.pending_payment(bob), 100);
+
+            // Nor do failed payouts block the settlement
+            test::set_block_timestamp::<DefaultEnvironment>(1_000 + AUCTION_EXTENSION_WINDOW);
+            assert_eq!(psp34.settle_auction(0), Ok(()));
+            assert_eq!(psp34.owner_of(Id::U32(0)), Some(charlie));
+            assert_eq!(psp34.pending_payment(artist), 200);
+
+            // Deferred payments are withdrawn by their recipients
+            assert_eq!(psp34.withdraw(), Err(Error::NothingToWithdraw));
+            test::set_account_balance::<DefaultEnvironment>(contract, 300);
+            set_caller(bob);
+            let bob_balance = balance_of(bob);
+            assert_eq!(psp34.withdraw(), Ok(()));
+            assert_eq!(balance_of(bob), bob_balance + 100);
+            assert_eq!(psp34.pending_payment(bob), 0);
+            set_caller(artist);
+            let artist_balance = balance_of(artist);
+            assert_eq!(psp34.withdraw(), Ok(()));
+            assert_eq!(balance_of(artist), artist_balance + 200);
+            assert_eq!(balance_of(contract), 0);
+        }
+
+        #[ink::test]
+        fn test_sealed_auction() {
+            let mut psp34 = with_artist();
+            let artist = accounts().alice;
+            let seller = accounts().django;
+            let contract = test::callee::<DefaultEnvironment>();
+            let balance_of = |account| test::get_account_balance::<DefaultEnvironment>(account).unwrap();
+
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            psp34.transfer(seller, Id::U32(0), Vec::new()).unwrap();
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+
+            // A second-price auction that slashes bidders who never reveal
+            set_caller(seller);
+            assert_eq!(
+                psp34.create_sealed_auction(0, 150, 2_000, 2_000, true, true),
+                Err(Error::InvalidAuction)
+            );
+            assert_eq!(psp34.create_sealed_auction(0, 150, 2_000, 3_000, true, true), Ok(()));
+            assert_eq!(psp34.owner_of(Id::U32(0)), Some(contract));
+
+            // Bidders commit with deposits covering their hidden bids
+            let (bob, charlie, eve, frank) = (accounts().bob, accounts().charlie, accounts().eve, accounts().frank);
+            let salt = Hash::from([7; 32]);
+            let bob_commitment = psp34.sealed_bid_commitment(0, bob, 300, salt);
+            set_caller(bob);
+            test::set_value_transferred::<DefaultEnvironment>(500);
+            assert_eq!(psp34.commit_bid(0, bob_commitment), Ok(()));
+            assert_eq!(psp34.commit_bid(0, bob_commitment), Err(Error::AlreadyCommitted));
+            set_caller(charlie);
+            test::set_value_transferred::<DefaultEnvironment>(200);
+            assert_eq!(psp34.commit_bid(0, psp34.sealed_bid_commitment(0, charlie, 200, salt)), Ok(()));
+            set_caller(eve);
+            test::set_value_transferred::<DefaultEnvironment>(100);
+            assert_eq!(psp34.commit_bid(0, psp34.sealed_bid_commitment(0, eve, 400, salt)), Ok(()));
+
+            // Copying the commitment of another bidder does not allow revealing their bid
+            set_caller(frank);
+            test::set_value_transferred::<DefaultEnvironment>(50);
+            assert_eq!(psp34.commit_bid(0, bob_commitment), Ok(()));
+
+            // Bids can only be revealed after the commit phase
+            set_caller(bob);
+            assert_eq!(psp34.reveal_bid(0, 300, salt), Err(Error::WrongPhase));
+            test::set_block_timestamp::<DefaultEnvironment>(2_000);
+            assert_eq!(psp34.commit_bid(0, Hash::default()), Err(Error::WrongPhase));
+
+            // Revealing refunds the part of the deposit above the bid, and any lower bid
+            set_caller(frank);
+            assert_eq!(psp34.reveal_bid(0, 300, salt), Err(Error::InvalidReveal));
+            set_caller(bob);
+            test::set_account_balance::<DefaultEnvironment>(contract, 850);
+            let (bob_balance, charlie_balance) = (balance_of(bob), balance_of(charlie));
+            assert_eq!(psp34.reveal_bid(0, 300, Hash::default()), Err(Error::InvalidReveal));
+            assert_eq!(psp34.reveal_bid(0, 300, salt), Ok(()));
+            assert_eq!(balance_of(bob), bob_balance + 200);
+            set_caller(charlie);
+            assert_eq!(psp34.reveal_bid(0, 200, salt), Ok(()));
+            assert_eq!(balance_of(charlie), charlie_balance + 200);
+
+            set_caller(seller);
+            assert_eq!(psp34.withdraw_unrevealed(0, eve), Err(Error::WrongPhase));
+            assert_eq!(psp34.settle_sealed_auction(0), Err(Error::AuctionNotEnded));
+
+            // The winner pays the second highest bid and gets the rest back
+            test::set_block_timestamp::<DefaultEnvironment>(3_000);
+            let (artist_balance, seller_balance) = (balance_of(artist), balance_of(seller));
+            assert_eq!(psp34.settle_sealed_auction(0), Ok(()));
+            assert_eq!(psp34.owner_of(Id::U32(0)), Some(bob));
+            assert_eq!(balance_of(artist), artist_balance + 20);
+            assert_eq!(balance_of(seller), seller_balance + 180);
+            assert_eq!(balance_of(bob), bob_balance + 300);
+
+            // The seller slashes the deposits of the bidders who never revealed
+            assert_eq!(psp34.withdraw_unrevealed(0, eve), Ok(()));
+            assert_eq!(psp34.withdraw_unrevealed(0, frank), Ok(()));
+            assert_eq!(balance_of(seller), seller_balance + 330);
+            assert_eq!(balance_of(contract), 0);
+            assert_eq!(psp34.withdraw_unrevealed(0, eve), Err(Error::BidNotFound));
+        }
+
+        #[ink::test]
+        fn test_dutch_auction() {
+            let mut psp34 = with_artist();
+            let artist = accounts().alice;
+            let buyer = accounts().bob;
+            let contract = test::callee::<DefaultEnvironment>();
+
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+
+            // The floor must be positive and below the start price
+            assert_eq!(psp34.list_dutch(0, 100, 200, 11_000, 0), Err(Error::InvalidPrice));
+            assert_eq!(psp34.list_dutch(0, 100, 0, 11_000, 0), Err(Error::InvalidPrice));
+            assert_eq!(psp34.list_dutch(0, 1_000, 200, 1_000, 0), Err(Error::InvalidExpiry));
+
+            // A linear decay from 1000 to 200 over 10 seconds
+            assert_eq!(psp34.list_dutch(0, 1_000, 200, 11_000, 0), Ok(()));
+            assert_eq!(psp34.current_price(0), Ok(1_000));
+            test::set_block_timestamp::<DefaultEnvironment>(3_500);
+            assert_eq!(psp34.current_price(0), Ok(800));
+            assert_eq!(psp34.update_listing(0, 900), Err(Error::DutchListing));
+            assert_eq!(psp34.set_price(0, 900), Err(Error::DutchListing));
+            assert_eq!(psp34.current_price(0), Ok(800));
+            test::set_block_timestamp::<DefaultEnvironment>(20_000);
+            assert_eq!(psp34.current_price(0), Ok(200));
+
+            // A stepwise decay only drops every 5 seconds
+            psp34.delist(0).unwrap();
+            test::set_block_timestamp::<DefaultEnvironment>(0);
+            assert_eq!(psp34.list_dutch(0, 1_000, 200, 10_000, 5_000), Ok(()));
+            test::set_block_timestamp::<DefaultEnvironment>(4_999);
+            assert_eq!(psp34.current_price(0), Ok(1_000));
+            test::set_block_timestamp::<DefaultEnvironment>(5_000);
+            assert_eq!(psp34.current_price(0), Ok(600));
+
+            // Buying pays the current price and refunds the rest
+            set_caller(buyer);
+            test::set_value_transferred::<DefaultEnvironment>(500);
+            assert_eq!(psp34.buy(0), Err(Error::WrongPrice));
+            let artist_balance = test::get_account_balance::<DefaultEnvironment>(artist).unwrap();
+            let buyer_balance = test::get_account_balance::<DefaultEnvironment>(buyer).unwrap();
+            test::set_account_balance::<DefaultEnvironment>(contract, 1_000);
+            test::set_value_transferred::<DefaultEnvironment>(1_000);
+            assert_eq!(psp34.buy(0), Ok(()));
+            assert_eq!(psp34.owner_of(Id::U32(0)), Some(buyer));
+            assert_eq!(
+                test::get_account_balance::<DefaultEnvironment>(artist),
+                Ok(artist_balance + 600)
+            );
+            assert_eq!(
+                test::get_account_balance::<DefaultEnvironment>(buyer),
+                Ok(buyer_balance + 400)
+            );
+            assert_eq!(psp34.current_price(0), Err(Error::NotForSale));
+        }
+
+        #[ink::test]
+        fn test_offers() {
+            let mut psp34 = with_artist();
+            let artist = accounts().alice;
+            let seller = accounts().django;
+            let (bob, charlie) = (accounts().bob, accounts().charlie);
+            let contract = test::callee::<DefaultEnvironment>();
+            let balance_of = |account| test::get_account_balance::<DefaultEnvironment>(account).unwrap();
+
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            psp34.transfer(seller, Id::U32(0), Vec::new()).unwrap();
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+
+            // Offers need funds and an expiry in the future
+            set_caller(bob);
+            assert_eq!(psp34.make_offer(0, 2_000), Err(Error::InvalidPrice));
+            test::set_value_transferred::<DefaultEnvironment>(100);
+            assert_eq!(psp34.make_offer(0, 1_000), Err(Error::InvalidExpiry));
+            assert_eq!(psp34.make_offer(1, 2_000), Err(Error::TokenNotFound));
+            assert_eq!(psp34.make_offer(0, 2_000), Ok(()));
+            set_caller(charlie);
+            test::set_value_transferred::<DefaultEnvironment>(50);
+            assert_eq!(psp34.make_offer(0, 5_000), Ok(()));
+            test::set_account_balance::<DefaultEnvironment>(contract, 150);
+
+            // Only the owner can accept, and only offers that have not expired
+            assert_eq!(psp34.accept_offer(0, bob), Err(Error::NotOwner));
+            set_caller(seller);
+            assert_eq!(psp34.accept_offer(0, accounts().eve), Err(Error::OfferNotFound));
+            test::set_block_timestamp::<DefaultEnvironment>(2_000);
+            assert_eq!(psp34.accept_offer(0, bob), Err(Error::OfferExpired));
+
+            // An expired offer is reclaimed by cancelling it
+            set_caller(bob);
+            let bob_balance = balance_of(bob);
+            assert_eq!(psp34.cancel_offer(0), Ok(()));
+            assert_eq!(balance_of(bob), bob_balance + 100);
+            assert_eq!(psp34.cancel_offer(0), Err(Error::OfferNotFound));
+
+            // Accepting sells the token for the escrowed amount
+            set_caller(seller);
+            let (artist_balance, seller_balance) = (balance_of(artist), balance_of(seller));
+            assert_eq!(psp34.accept_offer(0, charlie), Ok(()));
+            assert_eq!(psp34.owner_of(Id::U32(0)), Some(charlie));
+            assert_eq!(psp34.get_offer(0, charlie), None);
+            assert_eq!(balance_of(artist), artist_balance + 5);
+            assert_eq!(balance_of(seller), seller_balance + 45);
+            assert_eq!(balance_of(contract), 0);
+
+            // Offers do not carry over to a token reminted under the same id
+            set_caller(bob);
+            test::set_value_transferred::<DefaultEnvironment>(70);
+            assert_eq!(psp34.make_offer(0, 10_000), Ok(()));
+            test::set_account_balance::<DefaultEnvironment>(contract, 70);
+            set_caller(charlie);
+            assert_eq!(psp34.burn(0), Ok(()));
+            set_caller(artist);
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            assert_eq!(psp34.accept_offer(0, bob), Err(Error::OfferNotFound));
+
+            // The bidder still gets their funds back
+            set_caller(bob);
+            let bob_balance = balance_of(bob);
+            assert_eq!(psp34.cancel_offer(0), Ok(()));
+            assert_eq!(balance_of(bob), bob_balance + 70);
+        }
+
+        #[ink::test]
+        fn test_auction() {
+            let mut psp34 = with_artist();
+            let artist = accounts().alice;
+            let seller = accounts().django;
+            let contract = test::callee::<DefaultEnvironment>();
+
+            psp34.mint(0, 0, 0, TokenMetadata::default()).unwrap();
+            psp34.transfer(seller, Id::U32(0), Vec::new()).unwrap();
+            test::set_block_timestamp::<DefaultEnvironment>(1_000);
+
+            // Only the owner can auction the token, over a window that is not over yet
+            set_caller(accounts().bob);
+            assert_eq!(psp34.create_auction(0, 100, 2_000, 1_000_000, 10), Err(Error::NotOwner));
+            set_caller(seller);
+            assert_eq!(psp34.create_auction(0, 100, 0, 500, 10), Err(Error::InvalidAuction));
+            assert_eq!(psp34.create_auction(0, 100, 2_000, 1_000_000, 10), Ok(()));
+            assert_eq!(psp34.owner_of(Id::U32(0)), Some(contract));
+
+            // Bids must wait for the start and cover the reserve, then the increment
+            set_caller(accounts().bob);
+            test::set_value_transferred::<DefaultEnvironment>(100);
+            assert_eq!(psp34.bid(0), Err(Error::AuctionNotStarted));
+            test::set_block_timestamp::<DefaultEnvironment>(2_000);
+            test::set_value_transferred::<DefaultEnvironment>(90);
+            assert_eq!(psp34.bid(0), Err(Error::BidTooLow));
+            test::set_account_balance::<DefaultEnvironment>(contract, 100);
+            test::set_value_transferred::<DefaultEnvironment>(100);
+            assert_eq!(psp34.bid(0), Ok(()));
+
+            // The auction cannot be cancelled once it has a bid
+            set_caller(seller);
+            assert_eq!(psp34.cancel_auction(0), Err(Error::AuctionHasBids));
+
+            // Outbidding refunds the previous bidder
+            set_caller(accounts().charlie);
+            test::set_value_transferred::<DefaultEnvironment>(105);
+            assert_eq!(psp34.bid(0), Err(Error::BidTooLow));
+            let bob_balance = test::get_account_balance::<DefaultEnvironment>(accounts().bob).unwrap();
+            test::set_account_balance::<DefaultEnvironment>(contract, 300);
+            test::set_value_transferred::<DefaultEnvironment>(200);
+            assert_eq!(psp34.bid(0), Ok(()));
+            assert_eq!(
+                test::get_account_balance::<DefaultEnvironment>(accounts().bob),
+                Ok(bob_balance + 100)
+            );
+
+            // A bid right before the end extends the auction
+            set_caller(accounts().eve);
+            test::set_block_timestamp::<DefaultEnvironment>(999_000);
+            test::set_account_balance::<DefaultEnvironment>(contract, 500);
+            test::set_value_transferred::<DefaultEnvironment>(300);
+            assert_eq!(psp34.bid(0), Ok(()));
+            assert_eq!(psp34.get_auction(0).unwrap().end, 999_000 + AUCTION_EXTENSION_WINDOW);
+
+            test::set_block_timestamp::<DefaultEnvironment>(1_000_000);
+            assert_eq!(psp34.settle_auction(0), Err(Error::AuctionNotEnded));
+
+            // Settling hands the token to the winner and pays the artist and the seller
+            let artist_balance = test::get_account_balance::<DefaultEnvironment>(artist).unwrap();
+            let seller_balance = test::get_account_balance::<DefaultEnvironment>(seller).unwrap();
+            test::set_block_timestamp::<DefaultEnvironment>(999_000 + AUCTION_EXTENSION_WINDOW);
+            set_caller(accounts().bob);
+            assert_eq!(psp34.settle_auction(0), Ok(()));
+            assert_eq!(psp34.owner_of(Id::U32(0)), Some(accounts().eve));
+            assert_eq!(psp34.get_auction(0), None);
+            assert_eq!(
+                test::get_account_balance::<DefaultEnvironment>(artist),
+                Ok(artist_balance + 30)
+            );
+            assert_eq!(
+                test::get_account_balance::<DefaultEnvironment>(seller),
+                Ok(seller_balance + 270)
+            );
+            assert_eq!(test::get_account_balance::<DefaultEnvironment>(contract), Ok(0));
+            assert_eq!(psp34.settle_auction(0), Err(Error::AuctionNotFound));
+
+            // An auction without bids can be cancelled by the seller
+            set_caller(accounts().eve);
+            assert_eq!(psp34.create_auction(0, 100, 0, 2_000_000, 0), Ok(()));
+            set_caller(seller);
+            assert_eq!(psp34.cancel_auction(0), Err(Error::NotOwner));
+            set_caller(accounts().eve);
+            assert_eq!(psp34.cancel_auction(0), Ok(()));
+            assert_eq!(psp34.owner_of(Id::U32(0)), Some(accounts().eve));
+
+            // So can the operator who created it
+            assert_eq!(psp34.approve(accounts().frank, None, true), Ok(()));
+            set_caller(accounts().frank);
+            assert_eq!(psp34.create_auction(0, 100, 0, 2_000_000, 0), Ok(()));
+            assert_eq!(psp34.cancel_auction(0), Ok(()));
+            assert_eq!(psp34.owner_of(Id::U32(0)), Some(accounts().eve));
+        }
+    }
+}